@@ -0,0 +1,8 @@
+ALTER TABLE main.containers
+  DROP COLUMN IF EXISTS env,
+  DROP COLUMN IF EXISTS cmd,
+  DROP COLUMN IF EXISTS entrypoint,
+  DROP COLUMN IF EXISTS ports,
+  DROP COLUMN IF EXISTS mounts,
+  DROP COLUMN IF EXISTS working_dir,
+  DROP COLUMN IF EXISTS labels;
@@ -0,0 +1,8 @@
+ALTER TABLE main.containers
+  ADD COLUMN env TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN cmd TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN entrypoint TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN ports TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN mounts TEXT[] NOT NULL DEFAULT '{}',
+  ADD COLUMN working_dir VARCHAR(256),
+  ADD COLUMN labels TEXT[] NOT NULL DEFAULT '{}';
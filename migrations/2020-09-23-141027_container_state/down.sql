@@ -0,0 +1,2 @@
+ALTER TABLE main.containers
+  DROP COLUMN IF EXISTS state;
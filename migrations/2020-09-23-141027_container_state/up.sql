@@ -0,0 +1,2 @@
+ALTER TABLE main.containers
+  ADD COLUMN state VARCHAR(32) NOT NULL DEFAULT 'created';
@@ -0,0 +1,4 @@
+ALTER TABLE main.containers
+  DROP COLUMN IF EXISTS environment_id;
+
+DROP TABLE IF EXISTS main.environments;
@@ -0,0 +1,12 @@
+CREATE TABLE main.environments (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  name VARCHAR(128) NOT NULL UNIQUE CHECK (name <> ''),
+  description TEXT,
+  owner VARCHAR(128) NOT NULL CHECK (owner <> ''),
+  network VARCHAR(128),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE main.containers
+  ADD COLUMN environment_id UUID REFERENCES main.environments(id) ON DELETE SET NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE main.templates (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  name VARCHAR(128) NOT NULL UNIQUE CHECK (name <> ''),
+  description TEXT,
+  spec TEXT NOT NULL CHECK (spec <> ''),
+  parameters TEXT[] NOT NULL DEFAULT '{}',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
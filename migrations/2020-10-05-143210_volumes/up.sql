@@ -0,0 +1,7 @@
+CREATE TABLE main.volumes (
+  name VARCHAR(128) PRIMARY KEY CHECK (name <> ''),
+  driver VARCHAR(64) NOT NULL DEFAULT 'local',
+  environment_id UUID REFERENCES main.environments(id) ON DELETE SET NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
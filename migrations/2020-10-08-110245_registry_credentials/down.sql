@@ -0,0 +1 @@
+DROP TABLE IF EXISTS main.registry_credentials;
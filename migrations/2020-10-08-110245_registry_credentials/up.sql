@@ -0,0 +1,8 @@
+-- The password is encrypted with main.pgp_sym_encrypt, using a key held by the service.
+CREATE TABLE main.registry_credentials (
+  registry VARCHAR(256) PRIMARY KEY CHECK (registry <> ''),
+  username VARCHAR(256) NOT NULL CHECK (username <> ''),
+  password BYTEA NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS main.expirations;
+
+ALTER TABLE main.environments
+  DROP COLUMN IF EXISTS expires_at;
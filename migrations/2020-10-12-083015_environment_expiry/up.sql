@@ -0,0 +1,12 @@
+ALTER TABLE main.environments
+  ADD COLUMN expires_at TIMESTAMPTZ;
+
+-- The environments torn down by the expiry scheduler, and the containers removed with them.
+CREATE TABLE main.expirations (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  environment VARCHAR(128) NOT NULL,
+  owner VARCHAR(128) NOT NULL,
+  containers TEXT[] NOT NULL DEFAULT '{}',
+  expired_at TIMESTAMPTZ NOT NULL,
+  removed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
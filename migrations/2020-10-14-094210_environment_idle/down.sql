@@ -0,0 +1,3 @@
+ALTER TABLE main.environments
+  DROP COLUMN IF EXISTS stopped_at,
+  DROP COLUMN IF EXISTS active_at;
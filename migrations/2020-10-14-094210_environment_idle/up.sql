@@ -0,0 +1,5 @@
+-- The last time an environment was used, either through the API or by the activity of
+-- its containers, and when it was stopped for being idle.
+ALTER TABLE main.environments
+  ADD COLUMN active_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  ADD COLUMN stopped_at TIMESTAMPTZ;
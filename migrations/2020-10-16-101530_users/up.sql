@@ -0,0 +1,11 @@
+-- The password is stored hashed (argon2).
+CREATE TABLE main.users (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  username VARCHAR(64) NOT NULL UNIQUE CHECK (username <> ''),
+  email VARCHAR(128) NOT NULL UNIQUE CHECK (email <> ''),
+  password VARCHAR(256) NOT NULL CHECK (password <> ''),
+  roles TEXT[] NOT NULL DEFAULT '{}',
+  active BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
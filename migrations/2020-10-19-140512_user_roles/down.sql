@@ -0,0 +1,3 @@
+ALTER TABLE main.users
+  DROP CONSTRAINT IF EXISTS users_roles_check,
+  ALTER COLUMN roles SET DEFAULT '{}';
@@ -0,0 +1,4 @@
+-- New users can look, but not touch, until an admin grants them more roles.
+ALTER TABLE main.users
+  ALTER COLUMN roles SET DEFAULT '{viewer}',
+  ADD CONSTRAINT users_roles_check CHECK (roles <@ ARRAY['viewer', 'developer', 'admin']::TEXT[]);
@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS main.environment_shares;
+
+ALTER TABLE main.users
+  DROP COLUMN IF EXISTS teams;
+
+ALTER TABLE main.containers
+  DROP COLUMN IF EXISTS owner;
@@ -0,0 +1,16 @@
+-- The user who created the container. Containers created before users were recorded
+-- have none, and only admins can see them.
+ALTER TABLE main.containers
+  ADD COLUMN owner VARCHAR(128);
+
+ALTER TABLE main.users
+  ADD COLUMN teams TEXT[] NOT NULL DEFAULT '{}';
+
+-- Environments are visible to their owner, and to the users and teams they are shared with.
+CREATE TABLE main.environment_shares (
+  environment_id UUID NOT NULL REFERENCES main.environments(id) ON DELETE CASCADE,
+  kind VARCHAR(16) NOT NULL CHECK (kind IN ('user', 'team')),
+  grantee VARCHAR(128) NOT NULL CHECK (grantee <> ''),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (environment_id, kind, grantee)
+);
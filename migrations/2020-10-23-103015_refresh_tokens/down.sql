@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS main.revoked_tokens;
+
+DROP TABLE IF EXISTS main.refresh_tokens;
@@ -0,0 +1,21 @@
+-- Refresh tokens are only stored hashed. Rotating a token revokes it, and records the
+-- token which replaced it.
+CREATE TABLE main.refresh_tokens (
+  id UUID PRIMARY KEY DEFAULT main.gen_random_uuid(),
+  user_id UUID NOT NULL REFERENCES main.users(id) ON DELETE CASCADE,
+  token_hash BYTEA NOT NULL UNIQUE,
+  expires_at TIMESTAMPTZ NOT NULL,
+  revoked_at TIMESTAMPTZ,
+  replaced_by UUID REFERENCES main.refresh_tokens(id) ON DELETE SET NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_user_id_idx ON main.refresh_tokens (user_id);
+
+-- The access tokens revoked before their expiry, given by their id (jti).
+-- They can be forgotten once expired.
+CREATE TABLE main.revoked_tokens (
+  jti VARCHAR(64) PRIMARY KEY CHECK (jti <> ''),
+  expires_at TIMESTAMPTZ NOT NULL,
+  revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
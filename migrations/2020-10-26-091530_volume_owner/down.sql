@@ -0,0 +1,2 @@
+ALTER TABLE main.volumes
+  DROP COLUMN IF EXISTS owner;
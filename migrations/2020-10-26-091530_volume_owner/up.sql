@@ -0,0 +1,4 @@
+-- The user who owns the volume, ie the owner of its environment, or the user who created
+-- it. Volumes recorded before have none, and only admins can mount them.
+ALTER TABLE main.volumes
+  ADD COLUMN owner VARCHAR(128);
@@ -1,24 +1,46 @@
 use bollard::container::{
-    Config, CreateContainerOptions, ListContainersOptions, RemoveContainerOptions,
-    StartContainerOptions, StopContainerOptions,
+    Config, CreateContainerOptions, ListContainersOptions, NetworkingConfig,
+    RemoveContainerOptions, RestartContainerOptions, StartContainerOptions, StopContainerOptions,
 };
-use bollard::image::CreateImageOptions;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use futures::{future, TryFutureExt, TryStreamExt};
-use juniper::{GraphQLInputObject, GraphQLObject};
+use bollard::service::{
+    ContainerSummaryInner, EndpointSettings, HealthConfig, HostConfig, PortBinding,
+    RestartPolicy as RestartPolicyDocker, RestartPolicyNameEnum,
+};
+use futures::{future, TryFutureExt};
+use juniper::{GraphQLEnum, GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
 use slog::{info, warn};
 use snafu::ResultExt;
 use sqlx::Connection;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::default::Default;
+use std::time::Duration;
 
+use crate::api::engine::inspect_state;
 use crate::api::gql::Context;
+use crate::api::images::pull;
 use crate::api::model::*;
-use crate::db::model::ProvideData;
+use crate::api::policy::check_image;
+use crate::api::volumes::ensure_volume;
+use crate::auth::{Principal, Role};
+use crate::db::model::{ContainerEntity, ContainerSpec, EnvironmentEntity, ProvideData};
 use crate::db::Db;
 use crate::error;
+use crate::settings::{Limits, ResourceLimits};
+use crate::state::State;
+
+/// The label attached to every container created by this service, so that they can
+/// be told apart from the other containers running on the docker host.
+pub const MANAGED_LABEL: &str = "environments.managed";
+
+/// The label attached to the containers of an environment, with the environment name, so
+/// that they can be found even if they were not recorded.
+pub const ENVIRONMENT_LABEL: &str = "environments.environment";
+
+/// The labels this service attaches to containers are in this namespace, which users
+/// cannot use.
+const RESERVED_LABEL_PREFIX: &str = "environments.";
 
 /// The response body for single container
 /// It is optional, since we may be looking for a user which
@@ -57,17 +79,349 @@ impl From<Vec<Container>> for MultiContainersResponseBody {
 
 /// The query body for creating a new container
 #[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
 pub struct ContainerRequestBody {
     pub name: String,
     pub image: String,
+    pub env: Option<Vec<EnvVarRequestBody>>,
+    pub cmd: Option<Vec<String>>,
+    pub entrypoint: Option<Vec<String>>,
+    pub ports: Option<Vec<PortBindingRequestBody>>,
+    pub mounts: Option<Vec<MountRequestBody>>,
+    pub working_dir: Option<String>,
+    pub labels: Option<Vec<LabelRequestBody>>,
+    pub resources: Option<ResourcesRequestBody>,
+    pub restart_policy: Option<RestartPolicy>,
+    pub healthcheck: Option<HealthcheckRequestBody>,
+    /// Additional names under which the container can be reached on its
+    /// environment's network
+    pub aliases: Option<Vec<String>>,
+}
+
+/// The command the docker engine runs to check a container is healthy.
+/// The test follows the docker conventions, eg ["CMD", "pg_isready"] or
+/// ["CMD-SHELL", "curl -f http://localhost"].
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthcheckRequestBody {
+    pub test: Vec<String>,
+    pub interval_seconds: Option<i32>,
+    pub timeout_seconds: Option<i32>,
+    pub retries: Option<i32>,
+    pub start_period_seconds: Option<i32>,
+}
+
+impl From<&HealthcheckRequestBody> for HealthConfig {
+    fn from(healthcheck: &HealthcheckRequestBody) -> Self {
+        // The docker engine expects durations in nanoseconds.
+        let nanoseconds = |seconds: Option<i32>| seconds.map(|s| i64::from(s) * 1_000_000_000);
+        HealthConfig {
+            test: Some(healthcheck.test.clone()),
+            interval: nanoseconds(healthcheck.interval_seconds),
+            timeout: nanoseconds(healthcheck.timeout_seconds),
+            retries: healthcheck.retries.map(i64::from),
+            start_period: nanoseconds(healthcheck.start_period_seconds),
+        }
+    }
+}
+
+/// The resources requested for a new container.
+/// Memory is in megabytes, and the cpu quota in microseconds per 100ms period.
+/// Unspecified resources are given the service's default.
+#[derive(Debug, Default, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcesRequestBody {
+    pub memory_mb: Option<i32>,
+    pub cpu_shares: Option<i32>,
+    pub cpu_quota: Option<i32>,
+    pub pids_limit: Option<i32>,
+}
+
+/// What the docker engine does when a container exits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, GraphQLEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum RestartPolicy {
+    No,
+    Always,
+    UnlessStopped,
+    OnFailure,
+}
+
+impl From<RestartPolicy> for RestartPolicyNameEnum {
+    fn from(policy: RestartPolicy) -> Self {
+        match policy {
+            RestartPolicy::No => RestartPolicyNameEnum::NO,
+            RestartPolicy::Always => RestartPolicyNameEnum::ALWAYS,
+            RestartPolicy::UnlessStopped => RestartPolicyNameEnum::UNLESS_STOPPED,
+            RestartPolicy::OnFailure => RestartPolicyNameEnum::ON_FAILURE,
+        }
+    }
+}
+
+/// An environment variable given to a new container
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvVarRequestBody {
+    pub name: String,
+    pub value: String,
+}
+
+/// A container port to expose, and optionally bind to a host port.
+/// The protocol defaults to 'tcp'.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PortBindingRequestBody {
+    pub container_port: i32,
+    pub host_port: Option<i32>,
+    pub protocol: Option<String>,
+}
+
+impl PortBindingRequestBody {
+    /// The key used by the docker engine to identify this port, eg '80/tcp'
+    fn key(&self) -> String {
+        format!(
+            "{}/{}",
+            self.container_port,
+            self.protocol.as_deref().unwrap_or("tcp")
+        )
+    }
+}
+
+/// A mount for a new container.
+/// If the source is an absolute path, this is a bind mount, otherwise the source
+/// is the name of a volume.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MountRequestBody {
+    pub source: String,
+    pub target: String,
+    pub read_only: Option<bool>,
+}
+
+/// A label attached to a new container
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelRequestBody {
+    pub key: String,
+    pub value: String,
+}
+
+impl ContainerRequestBody {
+    /// The configuration of the container, as stored in the database.
+    pub fn spec(&self) -> ContainerSpec {
+        ContainerSpec {
+            env: self
+                .env
+                .iter()
+                .flatten()
+                .map(|env| format!("{}={}", env.name, env.value))
+                .collect(),
+            cmd: self.cmd.clone().unwrap_or_default(),
+            entrypoint: self.entrypoint.clone().unwrap_or_default(),
+            ports: self
+                .ports
+                .iter()
+                .flatten()
+                .map(|port| match port.host_port {
+                    Some(host_port) => format!("{}:{}", host_port, port.key()),
+                    None => port.key(),
+                })
+                .collect(),
+            mounts: self.binds(),
+            working_dir: self.working_dir.clone(),
+            labels: self
+                .labels
+                .iter()
+                .flatten()
+                .map(|label| format!("{}={}", label.key, label.value))
+                .collect(),
+        }
+    }
+
+    /// The resources allocated to the container: those requested, or the defaults.
+    /// Requests which are not positive, or exceed the maximum allowed, are rejected.
+    pub fn resources(&self, limits: &Limits) -> Result<ResourceLimits, error::Error> {
+        let requested = self.resources.as_ref();
+
+        Ok(ResourceLimits {
+            memory_mb: resolve_limit(
+                "memory (MB)",
+                requested.and_then(|r| r.memory_mb),
+                limits.default.memory_mb,
+                limits.maximum.memory_mb,
+            )?,
+            cpu_shares: resolve_limit(
+                "cpu shares",
+                requested.and_then(|r| r.cpu_shares),
+                limits.default.cpu_shares,
+                limits.maximum.cpu_shares,
+            )?,
+            cpu_quota: resolve_limit(
+                "cpu quota",
+                requested.and_then(|r| r.cpu_quota),
+                limits.default.cpu_quota,
+                limits.maximum.cpu_quota,
+            )?,
+            pids_limit: resolve_limit(
+                "pids limit",
+                requested.and_then(|r| r.pids_limit),
+                limits.default.pids_limit,
+                limits.maximum.pids_limit,
+            )?,
+        })
+    }
+
+    /// The configuration of the container, as expected by the docker engine.
+    pub fn config(&self, resources: &ResourceLimits) -> Config<String> {
+        let spec = self.spec();
+
+        let ports = self.ports.iter().flatten().collect::<Vec<_>>();
+
+        let exposed_ports = ports
+            .iter()
+            .map(|port| (port.key(), HashMap::new()))
+            .collect::<HashMap<_, _>>();
+
+        let mut port_bindings: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
+        ports.iter().for_each(|port| {
+            if let Some(host_port) = port.host_port {
+                port_bindings
+                    .entry(port.key())
+                    .or_insert_with(|| Some(Vec::new()))
+                    .get_or_insert_with(Vec::new)
+                    .push(PortBinding {
+                        host_ip: None,
+                        host_port: Some(host_port.to_string()),
+                    });
+            }
+        });
+
+        let mut labels = self
+            .labels
+            .iter()
+            .flatten()
+            .map(|label| (label.key.clone(), label.value.clone()))
+            .collect::<HashMap<_, _>>();
+        labels.insert(String::from(MANAGED_LABEL), String::from("true"));
+
+        let restart_policy = self.restart_policy.map(|policy| RestartPolicyDocker {
+            name: Some(RestartPolicyNameEnum::from(policy)),
+            ..Default::default()
+        });
+
+        let host_config = HostConfig {
+            binds: Some(spec.mounts),
+            port_bindings: Some(port_bindings),
+            memory: resources.memory_mb.map(|memory| memory * 1024 * 1024),
+            cpu_shares: resources.cpu_shares,
+            cpu_quota: resources.cpu_quota,
+            pids_limit: resources.pids_limit,
+            restart_policy,
+            ..Default::default()
+        };
+
+        Config {
+            image: Some(self.image.clone()),
+            env: Some(spec.env),
+            cmd: self.cmd.clone(),
+            entrypoint: self.entrypoint.clone(),
+            exposed_ports: Some(exposed_ports),
+            working_dir: spec.working_dir,
+            labels: Some(labels),
+            healthcheck: self.healthcheck.as_ref().map(HealthConfig::from),
+            host_config: Some(host_config),
+            ..Default::default()
+        }
+    }
+
+    /// The mounts in the 'source:target[:ro]' form used by the docker engine
+    fn binds(&self) -> Vec<String> {
+        self.mounts
+            .iter()
+            .flatten()
+            .map(|mount| {
+                if mount.read_only.unwrap_or(false) {
+                    format!("{}:{}:ro", mount.source, mount.target)
+                } else {
+                    format!("{}:{}", mount.source, mount.target)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Resolve the value of a resource: the one requested or the default one, making
+/// sure it does not exceed the maximum.
+fn resolve_limit(
+    resource: &str,
+    requested: Option<i32>,
+    default: Option<i64>,
+    maximum: Option<i64>,
+) -> Result<Option<i64>, error::Error> {
+    // The docker engine reads zero, or a negative value, as unlimited, which would
+    // bypass the maximum.
+    if let Some(requested) = requested.filter(|requested| *requested < 1) {
+        return Err(error::Error::ValidationError {
+            field: String::from(resource),
+            msg: format!("{} is not a positive value", requested),
+        });
+    }
+
+    let value = requested.map(i64::from).or(default);
+    match (value, maximum) {
+        (Some(value), Some(maximum)) if value > maximum => Err(error::Error::LimitExceeded {
+            resource: String::from(resource),
+            requested: value,
+            maximum,
+        }),
+        _ => Ok(value),
+    }
+}
+
+/// Bind mounts give a container access to the docker host, eg to its docker socket, so
+/// only admins can create them. Other users are left with named volumes.
+fn check_mounts(
+    container_request: &ContainerRequestBody,
+    principal: &Principal,
+) -> Result<(), error::Error> {
+    if principal.has_role(Role::Admin) {
+        return Ok(());
+    }
+    match container_request
+        .mounts
+        .iter()
+        .flatten()
+        .find(|mount| mount.source.starts_with('/'))
+    {
+        Some(mount) => Err(error::Error::Forbidden {
+            msg: format!("Only admins can bind mount host path {}", mount.source),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// The service trusts its own labels, eg to find the containers of an environment, so
+/// users cannot set labels in its namespace.
+fn check_labels(container_request: &ContainerRequestBody) -> Result<(), error::Error> {
+    match container_request
+        .labels
+        .iter()
+        .flatten()
+        .find(|label| label.key.starts_with(RESERVED_LABEL_PREFIX))
+    {
+        Some(label) => Err(error::Error::ValidationError {
+            field: String::from("labels"),
+            msg: format!("{} is reserved to this service", label.key),
+        }),
+        None => Ok(()),
+    }
 }
 
 /// Retrieve all containers
-/// This function will work in 4 steps.
+/// This function will work in 3 steps.
 /// 1. Get a list of containers from the database
-/// 2. Query the docker engine to get up-to-date information
-/// 3. Update the database
-/// 4. Formulate the response.
+/// 2. Query the docker engine to get up-to-date information (state, ports, health, ...)
+/// 3. Formulate the response.
 pub async fn list_containers(
     context: &Context,
 ) -> Result<MultiContainersResponseBody, error::Error> {
@@ -84,12 +438,22 @@ pub async fn list_containers(
                 msg: "could not initiate transaction",
             })?;
 
-        let entities = tx
-            .get_all_containers()
-            .await
-            .context(error::DBProvideError {
-                msg: "Could not get all them containers",
-            })?;
+        // Admins see all the containers, the others only their own, and the ones of the
+        // environments they can see.
+        let principal = context.principal()?;
+        let entities = if principal.has_role(Role::Admin) {
+            tx.get_all_containers()
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get all them containers",
+                })?
+        } else {
+            tx.get_containers_visible_to(&principal.username)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get visible containers",
+                })?
+        };
 
         let ids: Vec<&str> = entities
             .iter()
@@ -132,25 +496,43 @@ pub async fn list_containers(
                 msg: "Could not list containers",
             })?;
 
-        let containers = container_summaries
+        let mut container_summaries = container_summaries
             .into_iter()
-            .map(|summary| Container {
-                id: summary.id.unwrap_or(String::from("NA")),
-                name: summary
-                    .names
-                    .map(|names| names.into_iter().next().unwrap_or(String::from("NA")))
-                    .unwrap_or(String::from("NA")),
-                image: summary.image.unwrap_or(String::from("NA")),
-                created_at: DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(summary.created.unwrap_or(0), 0),
-                    Utc,
-                ),
-                updated_at: DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(summary.created.unwrap_or(0), 0),
-                    Utc,
-                ),
-            })
-            .collect::<Vec<_>>();
+            .filter_map(|summary| summary.id.clone().map(|id| (id, summary)))
+            .collect::<HashMap<_, _>>();
+
+        // We report the configuration stored in the database, completed with the
+        // runtime information for the containers the docker engine knows about.
+        // The others are reported as missing.
+        let containers = future::try_join_all(
+            entities
+                .into_iter()
+                .map(|entity| {
+                    let summary = container_summaries.remove(&entity.id);
+                    (entity, summary)
+                })
+                .map(|(entity, summary)| async move {
+                    let summary = match summary {
+                        Some(summary) => summary,
+                        None => {
+                            let mut container = Container::from(entity);
+                            container.state = ContainerState::Missing;
+                            return Ok(container);
+                        }
+                    };
+                    let inspect = inspect_state(&entity.id, &context.state).await?;
+                    let mut container = Container::from(entity);
+                    match inspect {
+                        Some(inspect) => {
+                            container = container.with_summary(summary).with_inspect(inspect)
+                        }
+                        // The container was removed since we listed them.
+                        None => container.state = ContainerState::Missing,
+                    }
+                    Ok::<_, error::Error>(container)
+                }),
+        )
+        .await?;
 
         Ok(MultiContainersResponseBody::from(containers))
     }
@@ -161,37 +543,77 @@ pub async fn list_containers(
 pub async fn create_container(
     container_request: ContainerRequestBody,
     context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    create_container_in_environment(container_request, None, context).await
+}
+
+/// Create a new container, possibly as a member of an environment, in which case
+/// the container joins the environment's network.
+pub async fn create_container_in_environment(
+    mut container_request: ContainerRequestBody,
+    environment: Option<&EnvironmentEntity>,
+    context: &Context,
 ) -> Result<SingleContainerResponseBody, error::Error> {
     async move {
-        let ContainerRequestBody { name, image } = container_request;
+        // The containers of an environment belong to the owner of the environment, which
+        // an admin may have created on behalf of someone else.
+        let owner = match environment {
+            Some(environment) => environment.owner.clone(),
+            None => context.principal()?.username.clone(),
+        };
 
-        info!(context.state.logger, "Creating image {}", &image);
+        // We check the image and the requested resources before doing any work.
+        container_request.image = check_image(
+            &container_request.image,
+            &context.state.policy,
+            &context.state.logger,
+        )?;
+        let resources = container_request.resources(&context.state.limits)?;
+        check_mounts(&container_request, context.principal()?)?;
+        check_labels(&container_request)?;
 
-        let options = Some(CreateImageOptions {
-            from_image: image.clone(),
-            ..Default::default()
-        });
+        let name = container_request.name.clone();
+        let image = container_request.image.clone();
 
-        context
-            .state
-            .docker
-            .create_image(options, None, None)
-            .try_for_each(|info| {
-                info!(context.state.logger, "image: {:?}", info);
-                future::ready(Ok(()))
-            })
-            .await
-            .context(error::BollardError {
-                msg: "Could not create image",
-            })?;
+        pull(&image, &context.state).await?;
+
+        // Named volumes are created beforehand, so that they are tracked, attached to
+        // the environment, and only mounted by the users who can manage them.
+        for mount in container_request.mounts.iter().flatten() {
+            if !mount.source.starts_with('/') {
+                ensure_volume(
+                    &mount.source,
+                    environment.map(|environment| environment.id),
+                    &owner,
+                    context,
+                )
+                .await?;
+            }
+        }
 
         let options = Some(CreateContainerOptions { name: name.clone() });
 
-        let config = Config {
-            image: Some(image.clone()),
-            //cmd: Some(vec!["/hello"]),
-            ..Default::default()
-        };
+        let mut config = container_request.config(&resources);
+
+        if let Some(environment) = environment {
+            config
+                .labels
+                .get_or_insert_with(HashMap::new)
+                .insert(String::from(ENVIRONMENT_LABEL), environment.name.clone());
+        }
+
+        if let Some(network) = environment.and_then(|environment| environment.network.clone()) {
+            if let Some(host_config) = config.host_config.as_mut() {
+                host_config.network_mode = Some(network.clone());
+            }
+            let endpoint = EndpointSettings {
+                aliases: container_request.aliases.clone(),
+                ..Default::default()
+            };
+            let mut endpoints_config = HashMap::new();
+            endpoints_config.insert(network, endpoint);
+            config.networking_config = Some(NetworkingConfig { endpoints_config });
+        }
 
         let resp = context
             .state
@@ -225,17 +647,33 @@ pub async fn create_container(
                 msg: "could not initiate transaction",
             })?;
 
-        let entity = ProvideData::create_container(
+        ProvideData::create_container(
             &mut tx as &mut sqlx::PgConnection,
             &resp.id,
             &name,
             &image,
+            &container_request.spec(),
+            environment.map(|environment| environment.id),
+            Some(&owner),
         )
         .await
         .context(error::DBProvideError {
             msg: "Could not create container",
         })?;
 
+        let entity = ProvideData::update_container_state(
+            &mut tx as &mut sqlx::PgConnection,
+            &name,
+            ContainerState::Running.as_str(),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not record container state",
+        })?
+        .ok_or_else(|| error::Error::MiscError {
+            msg: format!("Container {} vanished during creation", name),
+        })?;
+
         let container = Container::from(entity);
 
         tx.commit().await.context(error::DBError {
@@ -247,63 +685,715 @@ pub async fn create_container(
     .await
 }
 
-/// Delete a container
+/// Delete a container. Only its owner, or an admin, can delete it.
 pub async fn delete_container(
     name: &str,
     context: &Context,
 ) -> Result<SingleContainerResponseBody, error::Error> {
     async move {
-        let options = Some(StopContainerOptions {
-            t: 3, /* stop in 3s */
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::get_container_by_name(&mut tx as &mut sqlx::PgConnection, name)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get container by name",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        if let Some(entity) = entity {
+            let owner = entity.owner.unwrap_or_default();
+            if !context.principal()?.can_manage(&owner) {
+                return Err(error::Error::Forbidden {
+                    msg: format!(
+                        "Only the owner of container {} or an admin can delete it",
+                        name
+                    ),
+                });
+            }
+        }
+
+        let entity = remove_container(name, &context.state).await?;
+
+        Ok(SingleContainerResponseBody {
+            container: entity.map(Container::from),
+        })
+    }
+    .await
+}
+
+/// Stop and remove a managed container from the docker engine, and delete its record
+/// from the database.
+/// Containers which no longer exist in the docker engine are only deleted from the database,
+/// so that a stale record does not prevent the re-creation of a container with the same name.
+pub async fn remove_container(
+    name: &str,
+    state: &State,
+) -> Result<Option<ContainerEntity>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::get_container_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get container by name",
+        })?;
+
+    let entity = match entity {
+        Some(entity) => entity,
+        None => {
+            info!(
+                state.logger,
+                "Container {} is not managed, not removing", name
+            );
+            return Ok(None);
+        }
+    };
+
+    if let Some(summary) = docker_summary(&entity.id, state).await? {
+        if summary.state.as_deref() == Some(ContainerState::Running.as_str()) {
+            let options = Some(StopContainerOptions {
+                t: 3, /* stop in 3s */
+            });
+
+            state
+                .docker
+                .stop_container(name, options)
+                .await
+                .context(error::BollardError {
+                    msg: "Could not stop container",
+                })?;
+        }
+
+        let options = Some(RemoveContainerOptions {
+            force: true,
+            ..Default::default()
         });
 
-        context
-            .state
+        state
             .docker
-            .stop_container(&name, options)
+            .remove_container(name, options)
             .await
             .context(error::BollardError {
-                msg: "Could not stop container",
+                msg: "Could not remove container",
             })?;
+    } else {
+        warn!(
+            state.logger,
+            "Container {} no longer exists in the docker engine", name
+        );
+    }
+
+    let entity = ProvideData::delete_container_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete container",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit container deletion transaction",
+    })?;
+
+    Ok(entity)
+}
+
+/// Remove from the docker engine the containers labelled with an environment which are
+/// left once its recorded containers are removed, eg a container which was created while
+/// provisioning the environment, but failed to start, or to be recorded.
+pub async fn remove_unrecorded_containers(
+    environment: &str,
+    state: &State,
+) -> Result<(), error::Error> {
+    let label = format!("{}={}", ENVIRONMENT_LABEL, environment);
+    let mut filters = HashMap::new();
+    filters.insert("label", vec![label.as_str()]);
 
+    let options = Some(ListContainersOptions {
+        all: true,
+        filters,
+        ..Default::default()
+    });
+
+    let summaries = state
+        .docker
+        .list_containers(options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not list containers",
+        })?;
+
+    for id in summaries.into_iter().filter_map(|summary| summary.id) {
+        warn!(
+            state.logger,
+            "Removing unrecorded container {} of environment {}", id, environment
+        );
         let options = Some(RemoveContainerOptions {
             force: true,
             ..Default::default()
         });
+        state
+            .docker
+            .remove_container(&id, options)
+            .await
+            .context(error::BollardError {
+                msg: "Could not remove unrecorded container",
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Retrieve the docker engine's summary of a container, if the container still exists.
+pub async fn docker_summary(
+    id: &str,
+    state: &State,
+) -> Result<Option<ContainerSummaryInner>, error::Error> {
+    let mut filters = HashMap::new();
+    filters.insert("id", vec![id]);
+
+    let options = Some(ListContainersOptions {
+        all: true,
+        filters,
+        ..Default::default()
+    });
+
+    let summaries = state
+        .docker
+        .list_containers(options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not list containers",
+        })?;
+
+    Ok(summaries.into_iter().next())
+}
+
+/// Number of seconds we wait for a container's health check to pass.
+const HEALTHY_TIMEOUT: u64 = 120;
+
+/// Wait until the docker engine reports the container as healthy.
+/// Fails if the container becomes unhealthy, stops running, or does not become
+/// healthy in time.
+pub async fn wait_until_healthy(id: &str, state: &State) -> Result<(), error::Error> {
+    for _ in 0..HEALTHY_TIMEOUT {
+        let inspect_state =
+            inspect_state(id, state)
+                .await?
+                .ok_or_else(|| error::Error::MiscError {
+                    msg: format!("Container {} no longer exists", id),
+                })?;
+
+        if inspect_state.status.as_deref() != Some("running") {
+            return Err(error::Error::MiscError {
+                msg: format!("Container {} stopped before becoming healthy", id),
+            });
+        }
+
+        match inspect_state.health {
+            Some(HealthStatus::Healthy) => return Ok(()),
+            Some(HealthStatus::Unhealthy) => {
+                return Err(error::Error::MiscError {
+                    msg: format!("Container {} is unhealthy", id),
+                })
+            }
+            _ => tokio::time::delay_for(Duration::from_secs(1)).await,
+        }
+    }
+
+    Err(error::Error::MiscError {
+        msg: format!(
+            "Container {} did not become healthy within {} seconds",
+            id, HEALTHY_TIMEOUT
+        ),
+    })
+}
+
+/// Default number of seconds the docker engine waits for a container to stop
+/// before killing it.
+pub const DEFAULT_STOP_TIMEOUT: i32 = 10;
+
+/// The number of seconds to wait for a container to stop: the one requested, zero meaning
+/// killing it right away, or the default one.
+fn resolve_stop_timeout(timeout: Option<i32>) -> Result<i32, error::Error> {
+    match timeout {
+        Some(timeout) if timeout < 0 => Err(error::Error::ValidationError {
+            field: String::from("timeout"),
+            msg: format!("{} is not a number of seconds", timeout),
+        }),
+        Some(timeout) => Ok(timeout),
+        None => Ok(DEFAULT_STOP_TIMEOUT),
+    }
+}
+
+/// Start a stopped container
+pub async fn start_container(
+    name: &str,
+    context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    async move {
+        find_owned_container(name, context).await?;
 
         context
             .state
             .docker
-            .remove_container(&name, options)
+            .start_container(name, None::<StartContainerOptions<String>>)
             .await
             .context(error::BollardError {
-                msg: "Could not remove container",
+                msg: "Could not start container",
             })?;
 
-        let pool = &context.state.pool;
+        record_container_state(name, ContainerState::Running, &context.state).await
+    }
+    .await
+}
 
-        let mut tx = pool
-            .conn()
-            .and_then(Connection::begin)
+/// Stop a running container, without removing it, so that it can be started again
+/// with its state preserved.
+pub async fn stop_container(
+    name: &str,
+    timeout: Option<i32>,
+    context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    async move {
+        let timeout = resolve_stop_timeout(timeout)?;
+
+        find_owned_container(name, context).await?;
+
+        let options = Some(StopContainerOptions { t: timeout as _ });
+
+        context
+            .state
+            .docker
+            .stop_container(name, options)
             .await
-            .context(error::DBError {
-                msg: "could not initiate transaction",
+            .context(error::BollardError {
+                msg: "Could not stop container",
+            })?;
+
+        record_container_state(name, ContainerState::Exited, &context.state).await
+    }
+    .await
+}
+
+/// Restart a container
+pub async fn restart_container(
+    name: &str,
+    timeout: Option<i32>,
+    context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    async move {
+        let timeout = resolve_stop_timeout(timeout)?;
+
+        find_owned_container(name, context).await?;
+
+        let options = Some(RestartContainerOptions { t: timeout as _ });
+
+        context
+            .state
+            .docker
+            .restart_container(name, options)
+            .await
+            .context(error::BollardError {
+                msg: "Could not restart container",
+            })?;
+
+        record_container_state(name, ContainerState::Running, &context.state).await
+    }
+    .await
+}
+
+/// Pause all the processes of a container
+pub async fn pause_container(
+    name: &str,
+    context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    async move {
+        find_owned_container(name, context).await?;
+
+        context
+            .state
+            .docker
+            .pause_container(name)
+            .await
+            .context(error::BollardError {
+                msg: "Could not pause container",
+            })?;
+
+        record_container_state(name, ContainerState::Paused, &context.state).await
+    }
+    .await
+}
+
+/// Resume the processes of a paused container
+pub async fn unpause_container(
+    name: &str,
+    context: &Context,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    async move {
+        find_owned_container(name, context).await?;
+
+        context
+            .state
+            .docker
+            .unpause_container(name)
+            .await
+            .context(error::BollardError {
+                msg: "Could not unpause container",
+            })?;
+
+        record_container_state(name, ContainerState::Running, &context.state).await
+    }
+    .await
+}
+
+/// The names of the containers a user can see: their own, and those of the environments
+/// they can see.
+pub async fn visible_container_names(
+    username: &str,
+    state: &State,
+) -> Result<HashSet<String>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entities =
+        ProvideData::get_containers_visible_to(&mut tx as &mut sqlx::PgConnection, username)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get visible containers",
             })?;
 
-        let entity =
-            ProvideData::delete_container_by_name(&mut tx as &mut sqlx::PgConnection, &name)
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(entities.into_iter().map(|entity| entity.name).collect())
+}
+
+/// Retrieve the database record of a container the user making the request can operate,
+/// ie start, stop, or run commands in. Having its environment shared is not enough: only
+/// the owner of the container, or an admin, can do that.
+pub async fn find_owned_container(
+    name: &str,
+    context: &Context,
+) -> Result<ContainerEntity, error::Error> {
+    let entity = find_managed_container(name, context).await?;
+
+    let owner = entity.owner.clone().unwrap_or_default();
+    if context.principal()?.can_manage(&owner) {
+        Ok(entity)
+    } else {
+        Err(error::Error::Forbidden {
+            msg: format!(
+                "Only the owner of container {} or an admin can operate it",
+                name
+            ),
+        })
+    }
+}
+
+/// Retrieve the database record of a container, making sure it is managed by
+/// this service, and that the user making the request can use it.
+pub async fn find_managed_container(
+    name: &str,
+    context: &Context,
+) -> Result<ContainerEntity, error::Error> {
+    let pool = &context.state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::get_container_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get container by name",
+        })?
+        .ok_or_else(|| error::Error::MiscError {
+            msg: format!("Container {} is not managed by this service", name),
+        })?;
+
+    // Besides admins, a container can be used by its owner, and by the users who can see
+    // its environment.
+    let principal = context.principal()?;
+    let mut visible = principal.has_role(Role::Admin)
+        || entity.owner.as_deref() == Some(principal.username.as_str());
+    if !visible {
+        if let Some(environment_id) = entity.environment_id {
+            let environments = ProvideData::get_environments_visible_to(
+                &mut tx as &mut sqlx::PgConnection,
+                &principal.username,
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get visible environments",
+            })?;
+            visible = environments
+                .iter()
+                .any(|environment| environment.id == environment_id);
+        }
+    }
+    if !visible {
+        return Err(error::Error::Forbidden {
+            msg: format!(
+                "Container {} is not visible to {}",
+                name, principal.username
+            ),
+        });
+    }
+
+    // Using a container counts as activity of its environment, which is then not idle.
+    if let Some(environment_id) = entity.environment_id {
+        ProvideData::touch_environment(&mut tx as &mut sqlx::PgConnection, environment_id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not record environment activity",
+            })?;
+    }
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(entity)
+}
+
+/// Record the state of a container in the database, and return the updated container.
+/// Running containers resume their environment, if it was stopped.
+pub async fn record_container_state(
+    name: &str,
+    container_state: ContainerState,
+    state: &State,
+) -> Result<SingleContainerResponseBody, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::update_container_state(
+        &mut tx as &mut sqlx::PgConnection,
+        name,
+        container_state.as_str(),
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not record container state",
+    })?;
+
+    // An environment with a running container is not stopped anymore, and must be
+    // watched again for idleness.
+    if container_state == ContainerState::Running {
+        if let Some(environment_id) = entity.as_ref().and_then(|entity| entity.environment_id) {
+            ProvideData::resume_environment(&mut tx as &mut sqlx::PgConnection, environment_id)
                 .await
                 .context(error::DBProvideError {
-                    msg: "Could not delete container",
+                    msg: "Could not record environment start",
                 })?;
+        }
+    }
 
-        let container = entity.map(Container::from);
+    let container = entity.map(Container::from);
 
-        tx.commit().await.context(error::DBError {
-            msg: "could not commit container creation transaction",
-        })?;
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit container state transaction",
+    })?;
 
-        Ok(SingleContainerResponseBody { container })
+    Ok(SingleContainerResponseBody { container })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_limit_uses_the_default() {
+        assert_eq!(
+            resolve_limit("memory (MB)", None, Some(512), Some(4096)).unwrap(),
+            Some(512)
+        );
+        assert_eq!(
+            resolve_limit("cpu quota", None, None, Some(1000)).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn resolve_limit_accepts_requests_up_to_the_maximum() {
+        assert_eq!(
+            resolve_limit("memory (MB)", Some(4096), Some(512), Some(4096)).unwrap(),
+            Some(4096)
+        );
+        assert_eq!(
+            resolve_limit("memory (MB)", Some(8192), Some(512), None).unwrap(),
+            Some(8192)
+        );
+    }
+
+    #[test]
+    fn resolve_limit_rejects_requests_over_the_maximum() {
+        match resolve_limit("memory (MB)", Some(4097), Some(512), Some(4096)) {
+            Err(error::Error::LimitExceeded {
+                requested, maximum, ..
+            }) => {
+                assert_eq!(requested, 4097);
+                assert_eq!(maximum, 4096);
+            }
+            res => panic!("unexpected {:?}", res),
+        }
+    }
+
+    #[test]
+    fn resolve_limit_rejects_zero() {
+        assert!(matches!(
+            resolve_limit("memory (MB)", Some(0), Some(512), Some(4096)),
+            Err(error::Error::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn resolve_limit_rejects_negative_values() {
+        assert!(matches!(
+            resolve_limit("pids limit", Some(-1), Some(256), Some(1024)),
+            Err(error::Error::ValidationError { .. })
+        ));
+        assert!(matches!(
+            resolve_limit("cpu quota", Some(-1), None, None),
+            Err(error::Error::ValidationError { .. })
+        ));
+    }
+
+    fn principal(roles: &[&str]) -> Principal {
+        Principal {
+            subject: None,
+            username: String::from("alice"),
+            roles: roles.iter().map(|role| String::from(*role)).collect(),
+            token_id: None,
+            expires_at: None,
+        }
+    }
+
+    fn request(sources: &[&str]) -> ContainerRequestBody {
+        ContainerRequestBody {
+            name: String::from("app"),
+            image: String::from("postgres"),
+            env: None,
+            cmd: None,
+            entrypoint: None,
+            ports: None,
+            mounts: Some(
+                sources
+                    .iter()
+                    .map(|source| MountRequestBody {
+                        source: String::from(*source),
+                        target: String::from("/data"),
+                        read_only: None,
+                    })
+                    .collect(),
+            ),
+            working_dir: None,
+            labels: None,
+            resources: None,
+            restart_policy: None,
+            healthcheck: None,
+            aliases: None,
+        }
+    }
+
+    #[test]
+    fn resolve_stop_timeout_uses_the_default() {
+        assert_eq!(resolve_stop_timeout(None).unwrap(), DEFAULT_STOP_TIMEOUT);
+        assert_eq!(resolve_stop_timeout(Some(0)).unwrap(), 0);
+        assert_eq!(resolve_stop_timeout(Some(30)).unwrap(), 30);
+    }
+
+    #[test]
+    fn resolve_stop_timeout_rejects_negative_timeouts() {
+        assert!(matches!(
+            resolve_stop_timeout(Some(-1)),
+            Err(error::Error::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn check_mounts_accepts_named_volumes() {
+        assert!(check_mounts(&request(&["app-data"]), &principal(&["developer"])).is_ok());
+    }
+
+    #[test]
+    fn check_mounts_rejects_bind_mounts_of_non_admins() {
+        assert!(matches!(
+            check_mounts(
+                &request(&["app-data", "/var/run/docker.sock"]),
+                &principal(&["developer"])
+            ),
+            Err(error::Error::Forbidden { .. })
+        ));
+    }
+
+    #[test]
+    fn check_mounts_accepts_bind_mounts_of_admins() {
+        assert!(check_mounts(&request(&["/srv/data"]), &principal(&["admin"])).is_ok());
+    }
+
+    fn labelled(keys: &[&str]) -> ContainerRequestBody {
+        ContainerRequestBody {
+            labels: Some(
+                keys.iter()
+                    .map(|key| LabelRequestBody {
+                        key: String::from(*key),
+                        value: String::from("test"),
+                    })
+                    .collect(),
+            ),
+            ..request(&[])
+        }
+    }
+
+    #[test]
+    fn check_labels_accepts_user_labels() {
+        assert!(check_labels(&request(&[])).is_ok());
+        assert!(check_labels(&labelled(&["app", "com.example.environments"])).is_ok());
+    }
+
+    #[test]
+    fn check_labels_rejects_reserved_labels() {
+        for key in &[ENVIRONMENT_LABEL, MANAGED_LABEL, "environments.other"] {
+            assert!(matches!(
+                check_labels(&labelled(&["app", *key])),
+                Err(error::Error::ValidationError { .. })
+            ));
+        }
     }
-    .await
 }
@@ -0,0 +1,203 @@
+use hyper::client::conn::SendRequest;
+use hyper::header::HOST;
+use hyper::http::request::Builder;
+use hyper::{Body, Method, Request, StatusCode};
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, UnixStream};
+
+use crate::api::model::HealthStatus;
+use crate::error;
+use crate::state::docker::DockerHost;
+use crate::state::State;
+
+/// The runtime state of a container, as reported by the docker engine's inspection.
+/// The docker engine API version bollard is built for does not describe the health of
+/// containers, so we read it ourselves, along with the rest of the state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectState {
+    pub status: Option<String>,
+    pub health: Option<HealthStatus>,
+    pub exit_code: Option<i64>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectResponse {
+    #[serde(rename = "State")]
+    state: Option<StateResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateResponse {
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    #[serde(rename = "Health")]
+    health: Option<HealthResponse>,
+    #[serde(rename = "ExitCode")]
+    exit_code: Option<i64>,
+    #[serde(rename = "StartedAt")]
+    started_at: Option<String>,
+    #[serde(rename = "FinishedAt")]
+    finished_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    #[serde(rename = "Status")]
+    status: String,
+}
+
+impl From<InspectResponse> for InspectState {
+    fn from(response: InspectResponse) -> Self {
+        let state = response.state;
+        InspectState {
+            status: state.as_ref().and_then(|state| state.status.clone()),
+            exit_code: state.as_ref().and_then(|state| state.exit_code),
+            started_at: state.as_ref().and_then(|state| state.started_at.clone()),
+            finished_at: state.as_ref().and_then(|state| state.finished_at.clone()),
+            // Containers without health check report 'none'.
+            health: state
+                .and_then(|state| state.health)
+                .and_then(|health| HealthStatus::from_str(&health.status).ok()),
+        }
+    }
+}
+
+/// Inspect a container, if it still exists, and return its runtime state.
+pub async fn inspect_state(id: &str, state: &State) -> Result<Option<InspectState>, error::Error> {
+    let mut sender = connect(state).await?;
+
+    let request = request(Method::GET, &format!("/containers/{}/json", id))
+        .body(Body::empty())
+        .map_err(|err| error::Error::MiscError {
+            msg: format!("Could not build inspect request: {}", err),
+        })?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .context(error::HyperError {
+            msg: String::from("Could not inspect container"),
+        })?;
+
+    match response.status() {
+        StatusCode::OK => {}
+        StatusCode::NOT_FOUND => return Ok(None),
+        status => {
+            return Err(error::Error::MiscError {
+                msg: format!("Could not inspect container {}: {}", id, status),
+            })
+        }
+    }
+
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .context(error::HyperError {
+            msg: String::from("Could not read container inspection"),
+        })?;
+
+    let response: InspectResponse = serde_json::from_slice(&body).context(error::JSONError {
+        msg: String::from("Could not deserialize container inspection"),
+    })?;
+
+    Ok(Some(InspectState::from(response)))
+}
+
+/// Open a connection to the docker engine, for requests bollard cannot make.
+/// We connect the same way as bollard does.
+pub async fn connect(state: &State) -> Result<SendRequest<Body>, error::Error> {
+    match &state.docker_host {
+        DockerHost::Unix(path) => {
+            let stream = UnixStream::connect(path)
+                .await
+                .context(error::TokioIOError {
+                    msg: String::from("Could not connect to docker engine"),
+                })?;
+            handshake(stream).await
+        }
+        DockerHost::Tcp(addr) => {
+            let stream = TcpStream::connect(addr.as_str())
+                .await
+                .context(error::TokioIOError {
+                    msg: String::from("Could not connect to docker engine"),
+                })?;
+            handshake(stream).await
+        }
+    }
+}
+
+async fn handshake<T>(stream: T) -> Result<SendRequest<Body>, error::Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) =
+        hyper::client::conn::handshake(stream)
+            .await
+            .context(error::HyperError {
+                msg: String::from("Could not handshake with docker engine"),
+            })?;
+
+    // The connection drives the requests, and hands the socket over once upgraded.
+    tokio::spawn(connection);
+
+    Ok(sender)
+}
+
+/// Start a request to the docker engine.
+pub fn request(method: Method, path: &str) -> Builder {
+    Request::builder()
+        .method(method)
+        .uri(path)
+        .header(HOST, "docker")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inspect_state(json: &str) -> InspectState {
+        InspectState::from(serde_json::from_str::<InspectResponse>(json).unwrap())
+    }
+
+    #[test]
+    fn inspect_state_reads_health() {
+        let state = inspect_state(
+            r#"{"Id": "abcd", "State": {"Status": "running", "Health": {"Status": "healthy", "FailingStreak": 0, "Log": []}}}"#,
+        );
+        assert_eq!(state.status.as_deref(), Some("running"));
+        assert_eq!(state.health, Some(HealthStatus::Healthy));
+
+        let state =
+            inspect_state(r#"{"State": {"Status": "running", "Health": {"Status": "starting"}}}"#);
+        assert_eq!(state.health, Some(HealthStatus::Starting));
+
+        let state =
+            inspect_state(r#"{"State": {"Status": "running", "Health": {"Status": "unhealthy"}}}"#);
+        assert_eq!(state.health, Some(HealthStatus::Unhealthy));
+    }
+
+    #[test]
+    fn inspect_state_without_health_check() {
+        let state = inspect_state(r#"{"State": {"Status": "exited"}}"#);
+        assert_eq!(state.status.as_deref(), Some("exited"));
+        assert_eq!(state.health, None);
+
+        let state =
+            inspect_state(r#"{"State": {"Status": "running", "Health": {"Status": "none"}}}"#);
+        assert_eq!(state.health, None);
+    }
+
+    #[test]
+    fn inspect_state_reads_exit_code_and_times() {
+        let state = inspect_state(
+            r#"{"State": {"Status": "exited", "ExitCode": 137, "StartedAt": "2020-10-20T08:00:00Z", "FinishedAt": "2020-10-20T09:00:00Z"}}"#,
+        );
+        assert_eq!(state.exit_code, Some(137));
+        assert_eq!(state.started_at.as_deref(), Some("2020-10-20T08:00:00Z"));
+        assert_eq!(state.finished_at.as_deref(), Some("2020-10-20T09:00:00Z"));
+    }
+}
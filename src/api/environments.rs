@@ -0,0 +1,933 @@
+use bollard::container::{StartContainerOptions, StopContainerOptions};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use slog::{info, warn};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use crate::api::containers::{
+    create_container_in_environment, record_container_state, remove_container,
+    remove_unrecorded_containers, wait_until_healthy, ContainerRequestBody, DEFAULT_STOP_TIMEOUT,
+};
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::networks::{create_network, network_name, remove_network};
+use crate::auth::Role;
+use crate::db::model::{ContainerEntity, EntityId, EnvironmentEntity, ProvideData};
+use crate::db::Db;
+use crate::error;
+use crate::state::State;
+
+/// The response body for single environment
+/// It is optional, since we may be looking for an environment which
+/// does not match the query criteria.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleEnvironmentResponseBody {
+    pub environment: Option<Environment>,
+}
+
+impl From<Environment> for SingleEnvironmentResponseBody {
+    fn from(environment: Environment) -> Self {
+        Self {
+            environment: Some(environment),
+        }
+    }
+}
+
+/// The response body for multiple environments
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiEnvironmentsResponseBody {
+    pub environments: Vec<Environment>,
+    pub environments_count: i32,
+}
+
+impl From<Vec<Environment>> for MultiEnvironmentsResponseBody {
+    fn from(environments: Vec<Environment>) -> Self {
+        let environments_count = i32::try_from(environments.len()).unwrap();
+        Self {
+            environments,
+            environments_count,
+        }
+    }
+}
+
+/// The response body for the shares of an environment
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiSharesResponseBody {
+    pub shares: Vec<Share>,
+    pub shares_count: i32,
+}
+
+impl From<Vec<Share>> for MultiSharesResponseBody {
+    fn from(shares: Vec<Share>) -> Self {
+        let shares_count = i32::try_from(shares.len()).unwrap();
+        Self {
+            shares,
+            shares_count,
+        }
+    }
+}
+
+/// The query body for sharing an environment with a user, or a team, given by its name.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareRequestBody {
+    pub kind: ShareKind,
+    pub grantee: String,
+}
+
+/// The query body for creating a new environment.
+/// Each environment gets its own network, to which all its containers are attached.
+/// The environment is torn down automatically once it expires, either at the given
+/// time, or after the given time to live (in seconds).
+/// The environment belongs to the user creating it, unless an admin gives another owner.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentRequestBody {
+    pub name: String,
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    pub containers: Vec<ContainerRequestBody>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub ttl: Option<i32>,
+}
+
+impl EnvironmentRequestBody {
+    /// When the environment expires, if ever.
+    fn expiry(&self) -> Result<Option<DateTime<Utc>>, error::Error> {
+        match (self.expires_at, self.ttl) {
+            (Some(_), Some(_)) => Err(error::Error::MiscError {
+                msg: String::from("An environment expiry is given either by a time or a ttl"),
+            }),
+            (Some(expires_at), None) => {
+                if expires_at <= Utc::now() {
+                    return Err(error::Error::ValidationError {
+                        field: String::from("expiresAt"),
+                        msg: format!("{} is not in the future", expires_at),
+                    });
+                }
+                Ok(Some(expires_at))
+            }
+            (None, Some(ttl)) => {
+                check_seconds("ttl", ttl)?;
+                Ok(Some(Utc::now() + Duration::seconds(i64::from(ttl))))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+/// A time to live, or an extension, must be positive, or the environment would expire
+/// right away, or even earlier than it would have.
+fn check_seconds(field: &str, seconds: i32) -> Result<(), error::Error> {
+    if seconds < 1 {
+        return Err(error::Error::ValidationError {
+            field: String::from(field),
+            msg: format!("{} is not a positive number of seconds", seconds),
+        });
+    }
+    Ok(())
+}
+
+/// Retrieve all environments
+pub async fn list_environments(
+    context: &Context,
+) -> Result<MultiEnvironmentsResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        // Admins see all the environments, the others the ones they own, or which are
+        // shared with them.
+        let principal = context.principal()?;
+        let entities = if principal.has_role(Role::Admin) {
+            tx.get_all_environments()
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get all them environments",
+                })?
+        } else {
+            tx.get_environments_visible_to(&principal.username)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get visible environments",
+                })?
+        };
+
+        let mut environments = Vec::new();
+        for entity in entities {
+            let containers = tx.get_containers_by_environment(entity.id).await.context(
+                error::DBProvideError {
+                    msg: "Could not get environment containers",
+                },
+            )?;
+            environments.push(Environment::from((entity, containers)));
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(MultiEnvironmentsResponseBody::from(environments))
+    }
+    .await
+}
+
+/// Retrieve a single environment given its name
+pub async fn find_environment_by_name(
+    name: &str,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        let environment = load_environment(name, &context.state).await?;
+
+        if let Some((entity, _)) = environment.as_ref() {
+            authorize_access(entity, context).await?;
+            touch_environment(entity.id, &context.state).await?;
+        }
+
+        Ok(SingleEnvironmentResponseBody {
+            environment: environment.map(Environment::from),
+        })
+    }
+    .await
+}
+
+/// Create a new environment, and its containers.
+/// If one of the containers cannot be created, the whole environment is torn down.
+pub async fn create_environment(
+    environment_request: EnvironmentRequestBody,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    provision_environment(environment_request, &HashSet::new(), context).await
+}
+
+/// Create a new environment, and its containers, in the order they are given.
+/// The containers listed in `await_healthy` must be healthy before the next container
+/// is created.
+/// If one of the containers cannot be created, the whole environment is torn down.
+pub async fn provision_environment(
+    environment_request: EnvironmentRequestBody,
+    await_healthy: &HashSet<String>,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        let expires_at = environment_request.expiry()?;
+
+        let EnvironmentRequestBody {
+            name,
+            description,
+            owner,
+            containers,
+            ..
+        } = environment_request;
+
+        let owner = resolve_owner(owner, context)?;
+
+        let network = network_name(&name);
+
+        info!(context.state.logger, "Creating environment {}", name);
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::create_environment(
+            &mut tx as &mut sqlx::PgConnection,
+            &name,
+            description.as_deref(),
+            &owner,
+            Some(&network),
+            expires_at,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create environment",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit environment creation transaction",
+        })?;
+
+        let provisioned = async {
+            create_network(&network, &context.state).await?;
+            for container in containers {
+                let created =
+                    create_container_in_environment(container, Some(&entity), context).await?;
+                if let Some(container) = created.container {
+                    if await_healthy.contains(&container.name) {
+                        info!(
+                            context.state.logger,
+                            "Waiting for container {} to be healthy", container.name
+                        );
+                        wait_until_healthy(&container.id, &context.state).await?;
+                    }
+                }
+            }
+            Ok::<(), error::Error>(())
+        }
+        .await;
+
+        if let Err(err) = provisioned {
+            warn!(
+                context.state.logger,
+                "Could not create environment {}: {}", name, err
+            );
+            if let Err(err) = teardown_environment(&name, &context.state).await {
+                warn!(
+                    context.state.logger,
+                    "Could not tear down environment {}: {}", name, err
+                );
+            }
+            return Err(err);
+        }
+
+        let environment = load_environment(&name, &context.state).await?;
+
+        Ok(SingleEnvironmentResponseBody {
+            environment: environment.map(Environment::from),
+        })
+    }
+    .await
+}
+
+/// Push back the expiry of an environment by the given number of seconds.
+/// An environment which already expired, but was not torn down yet, gets the full
+/// extension from now.
+pub async fn extend_environment(
+    name: &str,
+    seconds: i32,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        check_seconds("seconds", seconds)?;
+
+        let (entity, _) = load_environment(name, &context.state)
+            .await?
+            .ok_or_else(|| error::Error::MiscError {
+                msg: format!("Environment {} does not exist", name),
+            })?;
+
+        authorize(&entity.owner, context)?;
+
+        let expires_at = entity.expires_at.ok_or_else(|| error::Error::MiscError {
+            msg: format!("Environment {} does not expire", name),
+        })?;
+
+        let expires_at =
+            std::cmp::max(expires_at, Utc::now()) + Duration::seconds(i64::from(seconds));
+
+        info!(
+            context.state.logger,
+            "Extending environment {} until {}", name, expires_at
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        ProvideData::update_environment_expiry(
+            &mut tx as &mut sqlx::PgConnection,
+            name,
+            Some(expires_at),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not update environment expiry",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit environment expiry transaction",
+        })?;
+
+        touch_environment(entity.id, &context.state).await?;
+
+        let environment = load_environment(name, &context.state).await?;
+
+        Ok(SingleEnvironmentResponseBody {
+            environment: environment.map(Environment::from),
+        })
+    }
+    .await
+}
+
+/// Retrieve the users and teams an environment is shared with
+pub async fn list_environment_shares(
+    name: &str,
+    context: &Context,
+) -> Result<MultiSharesResponseBody, error::Error> {
+    async move {
+        let (entity, _) = load_environment(name, &context.state)
+            .await?
+            .ok_or_else(|| error::Error::MiscError {
+                msg: format!("Environment {} does not exist", name),
+            })?;
+
+        authorize_access(&entity, context).await?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entities =
+            ProvideData::get_environment_shares(&mut tx as &mut sqlx::PgConnection, entity.id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get environment shares",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let shares = entities.into_iter().map(Share::from).collect::<Vec<_>>();
+
+        Ok(MultiSharesResponseBody::from(shares))
+    }
+    .await
+}
+
+/// Share an environment with a user, or a team, so that they can see and use it.
+/// Only the owner of the environment, or an admin, can share it.
+pub async fn share_environment(
+    name: &str,
+    share: ShareRequestBody,
+    context: &Context,
+) -> Result<MultiSharesResponseBody, error::Error> {
+    async move {
+        let (entity, _) = load_environment(name, &context.state)
+            .await?
+            .ok_or_else(|| error::Error::MiscError {
+                msg: format!("Environment {} does not exist", name),
+            })?;
+
+        authorize(&entity.owner, context)?;
+
+        info!(
+            context.state.logger,
+            "Sharing environment {} with {} {}",
+            name,
+            share.kind.as_str(),
+            share.grantee
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        ProvideData::create_environment_share(
+            &mut tx as &mut sqlx::PgConnection,
+            entity.id,
+            share.kind.as_str(),
+            &share.grantee,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not share environment",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit environment share transaction",
+        })?;
+
+        list_environment_shares(name, context).await
+    }
+    .await
+}
+
+/// Stop sharing an environment with a user, or a team.
+/// Only the owner of the environment, or an admin, can unshare it.
+pub async fn unshare_environment(
+    name: &str,
+    share: ShareRequestBody,
+    context: &Context,
+) -> Result<MultiSharesResponseBody, error::Error> {
+    async move {
+        let (entity, _) = load_environment(name, &context.state)
+            .await?
+            .ok_or_else(|| error::Error::MiscError {
+                msg: format!("Environment {} does not exist", name),
+            })?;
+
+        authorize(&entity.owner, context)?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        ProvideData::delete_environment_share(
+            &mut tx as &mut sqlx::PgConnection,
+            entity.id,
+            share.kind.as_str(),
+            &share.grantee,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not unshare environment",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit environment share transaction",
+        })?;
+
+        list_environment_shares(name, context).await
+    }
+    .await
+}
+
+/// Start again the containers of a stopped environment, in the order they were created.
+/// The environment is then considered active, so it is not stopped again before being
+/// idle for the configured period.
+pub async fn start_environment(
+    name: &str,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        let (entity, containers) =
+            load_environment(name, &context.state)
+                .await?
+                .ok_or_else(|| error::Error::MiscError {
+                    msg: format!("Environment {} does not exist", name),
+                })?;
+
+        authorize_access(&entity, context).await?;
+
+        info!(context.state.logger, "Starting environment {}", name);
+
+        for container in containers.iter().filter(|container| {
+            container.state == ContainerState::Created.as_str()
+                || container.state == ContainerState::Exited.as_str()
+        }) {
+            context
+                .state
+                .docker
+                .start_container(&container.name, None::<StartContainerOptions<String>>)
+                .await
+                .context(error::BollardError {
+                    msg: "Could not start container",
+                })?;
+
+            record_container_state(&container.name, ContainerState::Running, &context.state)
+                .await?;
+        }
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        ProvideData::update_environment_stopped(&mut tx as &mut sqlx::PgConnection, name, None)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not record environment start",
+            })?;
+
+        ProvideData::touch_environment(&mut tx as &mut sqlx::PgConnection, entity.id)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not record environment activity",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit environment start transaction",
+        })?;
+
+        let environment = load_environment(name, &context.state).await?;
+
+        Ok(SingleEnvironmentResponseBody {
+            environment: environment.map(Environment::from),
+        })
+    }
+    .await
+}
+
+/// Delete an environment, and all its containers
+pub async fn delete_environment(
+    name: &str,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        if let Some((entity, _)) = load_environment(name, &context.state).await? {
+            authorize(&entity.owner, context)?;
+        }
+
+        let environment = teardown_environment(name, &context.state).await?;
+
+        Ok(SingleEnvironmentResponseBody {
+            environment: environment.map(Environment::from),
+        })
+    }
+    .await
+}
+
+/// Retrieve an environment and its containers from the database.
+pub async fn load_environment(
+    name: &str,
+    state: &State,
+) -> Result<Option<(EnvironmentEntity, Vec<ContainerEntity>)>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::get_environment_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get environment by name",
+        })?;
+
+    let environment = match entity {
+        None => None,
+        Some(entity) => {
+            let containers = ProvideData::get_containers_by_environment(
+                &mut tx as &mut sqlx::PgConnection,
+                entity.id,
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get environment containers",
+            })?;
+            Some((entity, containers))
+        }
+    };
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(environment)
+}
+
+/// Remove all the containers of an environment, and delete the environment.
+/// The removed containers are returned along with the environment.
+pub async fn teardown_environment(
+    name: &str,
+    state: &State,
+) -> Result<Option<(EnvironmentEntity, Vec<ContainerEntity>)>, error::Error> {
+    let (entity, containers) = match load_environment(name, state).await? {
+        Some(environment) => environment,
+        None => return Ok(None),
+    };
+
+    info!(state.logger, "Tearing down environment {}", name);
+
+    let mut removed = Vec::new();
+    for container in containers {
+        if let Some(container) = remove_container(&container.name, state).await? {
+            removed.push(container);
+        }
+    }
+
+    // The network cannot be removed while a container is attached to it.
+    remove_unrecorded_containers(name, state).await?;
+
+    if let Some(network) = entity.network.as_deref() {
+        remove_network(network, state).await?;
+    }
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    ProvideData::delete_environment_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete environment",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit environment deletion transaction",
+    })?;
+
+    Ok(Some((entity, removed)))
+}
+
+/// Stop the running containers of an environment, in the reverse order of their creation,
+/// without removing them, and record when the environment was stopped.
+pub async fn stop_environment(
+    name: &str,
+    state: &State,
+) -> Result<Option<EnvironmentEntity>, error::Error> {
+    let (_, containers) = match load_environment(name, state).await? {
+        Some(environment) => environment,
+        None => return Ok(None),
+    };
+
+    info!(state.logger, "Stopping environment {}", name);
+
+    for container in containers.iter().rev().filter(|container| {
+        container.state == ContainerState::Running.as_str()
+            || container.state == ContainerState::Paused.as_str()
+    }) {
+        let options = Some(StopContainerOptions {
+            t: DEFAULT_STOP_TIMEOUT as _,
+        });
+
+        state
+            .docker
+            .stop_container(&container.name, options)
+            .await
+            .context(error::BollardError {
+                msg: "Could not stop container",
+            })?;
+
+        record_container_state(&container.name, ContainerState::Exited, state).await?;
+    }
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::update_environment_stopped(
+        &mut tx as &mut sqlx::PgConnection,
+        name,
+        Some(Utc::now()),
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not record environment stop",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit environment stop transaction",
+    })?;
+
+    Ok(entity)
+}
+
+/// Developers can only create and manage their own environments, while admins manage
+/// everybody's.
+fn authorize(owner: &str, context: &Context) -> Result<(), error::Error> {
+    if context.principal()?.can_manage(owner) {
+        Ok(())
+    } else {
+        Err(error::Error::Forbidden {
+            msg: format!("Only {} or an admin can manage this environment", owner),
+        })
+    }
+}
+
+/// The owner of a new environment: the user making the request, unless an admin creates
+/// it on behalf of someone else.
+fn resolve_owner(owner: Option<String>, context: &Context) -> Result<String, error::Error> {
+    let principal = context.principal()?;
+    match owner {
+        None => Ok(principal.username.clone()),
+        Some(owner) if owner == principal.username || principal.has_role(Role::Admin) => Ok(owner),
+        Some(owner) => Err(error::Error::Forbidden {
+            msg: format!("Only an admin can create an environment for {}", owner),
+        }),
+    }
+}
+
+/// Besides admins, an environment can be seen and used by its owner, and by the users it
+/// is shared with, directly or through one of their teams.
+async fn authorize_access(
+    entity: &EnvironmentEntity,
+    context: &Context,
+) -> Result<(), error::Error> {
+    let principal = context.principal()?;
+    if principal.can_manage(&entity.owner) {
+        return Ok(());
+    }
+
+    let pool = &context.state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let environments = ProvideData::get_environments_visible_to(
+        &mut tx as &mut sqlx::PgConnection,
+        &principal.username,
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not get visible environments",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    if environments
+        .iter()
+        .any(|environment| environment.id == entity.id)
+    {
+        Ok(())
+    } else {
+        Err(error::Error::Forbidden {
+            msg: format!(
+                "Environment {} is not shared with {}",
+                entity.name, principal.username
+            ),
+        })
+    }
+}
+
+/// Record that an environment was used, either through the API, or by its containers.
+pub async fn touch_environment(id: EntityId, state: &State) -> Result<(), error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    ProvideData::touch_environment(&mut tx as &mut sqlx::PgConnection, id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not record environment activity",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit environment activity transaction",
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(expires_at: Option<DateTime<Utc>>, ttl: Option<i32>) -> EnvironmentRequestBody {
+        EnvironmentRequestBody {
+            name: String::from("test"),
+            description: None,
+            owner: None,
+            containers: Vec::new(),
+            expires_at,
+            ttl,
+        }
+    }
+
+    #[test]
+    fn expiry_defaults_to_never() {
+        assert_eq!(request(None, None).expiry().unwrap(), None);
+    }
+
+    #[test]
+    fn expiry_from_time() {
+        let expires_at = Utc::now() + Duration::hours(1);
+        assert_eq!(
+            request(Some(expires_at), None).expiry().unwrap(),
+            Some(expires_at)
+        );
+    }
+
+    #[test]
+    fn expiry_from_ttl() {
+        let before = Utc::now();
+        let expires_at = request(None, Some(60)).expiry().unwrap().unwrap();
+        assert!(expires_at >= before + Duration::seconds(60));
+        assert!(expires_at <= Utc::now() + Duration::seconds(60));
+    }
+
+    #[test]
+    fn expiry_rejects_time_and_ttl() {
+        assert!(request(Some(Utc::now()), Some(60)).expiry().is_err());
+    }
+
+    #[test]
+    fn expiry_rejects_time_in_the_past() {
+        for expires_at in &[Utc::now(), Utc::now() - Duration::hours(1)] {
+            assert!(matches!(
+                request(Some(*expires_at), None).expiry(),
+                Err(error::Error::ValidationError { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn expiry_rejects_ttl_not_positive() {
+        for ttl in &[0, -60] {
+            assert!(matches!(
+                request(None, Some(*ttl)).expiry(),
+                Err(error::Error::ValidationError { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn check_seconds_accepts_positive() {
+        assert!(check_seconds("seconds", 1).is_ok());
+        assert!(matches!(
+            check_seconds("seconds", 0),
+            Err(error::Error::ValidationError { .. })
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+use bollard::system::EventsOptions;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::collections::HashMap;
+
+use crate::api::containers::{visible_container_names, MANAGED_LABEL};
+use crate::error;
+use crate::state::State;
+
+/// An event emitted by the docker engine for a container managed by this service,
+/// eg 'start', 'die', 'oom', or 'health_status: unhealthy'
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerEvent {
+    pub id: String,
+    pub name: String,
+    pub image: Option<String>,
+    pub action: String,
+    pub exit_code: Option<i32>,
+    pub time: DateTime<Utc>,
+}
+
+/// Stream the docker engine events of the containers managed by this service.
+/// Given a username, the events are restricted to the containers this user can see, which
+/// are looked up on each event, since containers come and go.
+pub fn container_events(
+    username: Option<String>,
+    state: &State,
+) -> impl Stream<Item = Result<ContainerEvent, error::Error>> + Send + 'static {
+    let mut filters = HashMap::new();
+    filters.insert("type", vec!["container"]);
+    filters.insert("label", vec![MANAGED_LABEL]);
+
+    let options = Some(EventsOptions {
+        filters,
+        ..Default::default()
+    });
+
+    let visibility = state.clone();
+
+    state
+        .docker
+        .events(options)
+        .map_ok(|event| {
+            let (id, mut attributes) = event
+                .actor
+                .map(|actor| {
+                    (
+                        actor.id.unwrap_or_default(),
+                        actor.attributes.unwrap_or_default(),
+                    )
+                })
+                .unwrap_or_default();
+            ContainerEvent {
+                id,
+                name: attributes.remove("name").unwrap_or_default(),
+                image: attributes.remove("image"),
+                action: event.action.unwrap_or_default(),
+                exit_code: attributes
+                    .remove("exitCode")
+                    .and_then(|code| code.parse::<i32>().ok()),
+                time: DateTime::<Utc>::from_utc(
+                    NaiveDateTime::from_timestamp(event.time.unwrap_or(0), 0),
+                    Utc,
+                ),
+            }
+        })
+        .map_err(|err| error::Error::BollardError {
+            msg: String::from("Could not stream docker events"),
+            source: err,
+        })
+        .try_filter_map(move |event| {
+            let username = username.clone();
+            let state = visibility.clone();
+            async move {
+                match username {
+                    None => Ok(Some(event)),
+                    Some(username) => {
+                        let visible = visible_container_names(&username, &state).await?;
+                        Ok(Some(event).filter(|event| visible.contains(&event.name)))
+                    }
+                }
+            }
+        })
+}
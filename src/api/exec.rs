@@ -0,0 +1,97 @@
+use bollard::container::LogOutput;
+use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
+use futures::TryStreamExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use slog::info;
+use snafu::ResultExt;
+
+use crate::api::containers::{find_owned_container, EnvVarRequestBody};
+use crate::api::gql::Context;
+use crate::error;
+
+/// The response body for a command run in a container
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecResponseBody {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Run a command in a managed container, wait for its completion, and return its output.
+pub async fn exec_in_container(
+    name: &str,
+    cmd: Vec<String>,
+    env: Option<Vec<EnvVarRequestBody>>,
+    working_dir: Option<String>,
+    context: &Context,
+) -> Result<ExecResponseBody, error::Error> {
+    async move {
+        find_owned_container(name, context).await?;
+
+        info!(context.state.logger, "Running {:?} in {}", cmd, name);
+
+        let env = env.map(|env| {
+            env.into_iter()
+                .map(|env| format!("{}={}", env.name, env.value))
+                .collect::<Vec<_>>()
+        });
+
+        let options = CreateExecOptions {
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            cmd: Some(cmd),
+            env,
+            working_dir,
+            ..Default::default()
+        };
+
+        let exec = context
+            .state
+            .docker
+            .create_exec(name, options)
+            .await
+            .context(error::BollardError {
+                msg: "Could not create exec",
+            })?;
+
+        let outputs = context
+            .state
+            .docker
+            .start_exec(&exec.id, None::<StartExecOptions>)
+            .try_collect::<Vec<_>>()
+            .await
+            .context(error::BollardError {
+                msg: "Could not start exec",
+            })?;
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        outputs.into_iter().for_each(|output| {
+            if let StartExecResults::Attached { log } = output {
+                match log {
+                    LogOutput::StdErr { .. } => stderr.push_str(&log.to_string()),
+                    _ => stdout.push_str(&log.to_string()),
+                }
+            }
+        });
+
+        let inspect =
+            context
+                .state
+                .docker
+                .inspect_exec(&exec.id)
+                .await
+                .context(error::BollardError {
+                    msg: "Could not inspect exec",
+                })?;
+
+        Ok(ExecResponseBody {
+            exit_code: inspect.exit_code.map(|code| code as i32),
+            stdout,
+            stderr,
+        })
+    }
+    .await
+}
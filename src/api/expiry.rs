@@ -0,0 +1,195 @@
+use chrono::Utc;
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use slog::{info, warn};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use crate::api::environments::teardown_environment;
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::auth::Role;
+use crate::db::model::{EnvironmentEntity, ExpirationEntity, ProvideData};
+use crate::db::Db;
+use crate::error;
+use crate::settings;
+use crate::state::State;
+
+/// The response body for multiple expirations
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiExpirationsResponseBody {
+    pub expirations: Vec<Expiration>,
+    pub expirations_count: i32,
+}
+
+impl From<Vec<Expiration>> for MultiExpirationsResponseBody {
+    fn from(expirations: Vec<Expiration>) -> Self {
+        let expirations_count = i32::try_from(expirations.len()).unwrap();
+        Self {
+            expirations,
+            expirations_count,
+        }
+    }
+}
+
+/// Retrieve the environments torn down because they expired, most recent first.
+/// Users other than admins only see their own environments.
+pub async fn list_expirations(
+    context: &Context,
+) -> Result<MultiExpirationsResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        // Admins see all the expirations, the others those of their own environments.
+        // The shares of an environment are gone with it.
+        let principal = context.principal()?;
+        let entities = if principal.has_role(Role::Admin) {
+            tx.get_all_expirations()
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get all them expirations",
+                })?
+        } else {
+            tx.get_expirations_by_owner(&principal.username)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get expirations by owner",
+                })?
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let expirations = entities
+            .into_iter()
+            .map(Expiration::from)
+            .collect::<Vec<_>>();
+
+        Ok(MultiExpirationsResponseBody::from(expirations))
+    }
+    .await
+}
+
+/// Tear down the environments which expired, the same way they are deleted on request,
+/// and record what was removed.
+pub async fn expire_environments(state: &State) -> Result<Vec<ExpirationEntity>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let expired =
+        ProvideData::get_expired_environments(&mut tx as &mut sqlx::PgConnection, Utc::now())
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get expired environments",
+            })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    // One environment failing to be torn down must not keep the others alive: it is
+    // logged, and tried again at the next run.
+    let mut expirations = Vec::new();
+    for environment in expired {
+        info!(state.logger, "Environment {} expired", environment.name);
+
+        match expire_environment(&environment, state).await {
+            Ok(Some(expiration)) => expirations.push(expiration),
+            Ok(None) => {}
+            Err(err) => warn!(
+                state.logger,
+                "Could not tear down expired environment {}: {}", environment.name, err
+            ),
+        }
+    }
+
+    Ok(expirations)
+}
+
+/// Tear down an expired environment, and record its expiration.
+/// Nothing is recorded if the environment is already gone.
+async fn expire_environment(
+    environment: &EnvironmentEntity,
+    state: &State,
+) -> Result<Option<ExpirationEntity>, error::Error> {
+    let removed = match teardown_environment(&environment.name, state).await? {
+        Some((_, containers)) => containers,
+        None => return Ok(None),
+    };
+
+    let containers = removed
+        .into_iter()
+        .map(|container| container.name)
+        .collect::<Vec<_>>();
+
+    let mut tx = state
+        .pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let expiration = ProvideData::create_expiration(
+        &mut tx as &mut sqlx::PgConnection,
+        &environment.name,
+        &environment.owner,
+        &containers,
+        environment.expires_at.unwrap_or_else(Utc::now),
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not record expiration",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit expiration transaction",
+    })?;
+
+    Ok(Some(expiration))
+}
+
+/// Periodically tear down the expired environments.
+/// This is meant to be spawned in the background when the server starts.
+pub async fn run_expiry(state: State, settings: settings::Expiry) {
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.interval));
+    loop {
+        interval.tick().await;
+        match expire_environments(&state).await {
+            Ok(expirations) => {
+                for expiration in expirations {
+                    info!(
+                        state.logger,
+                        "Tore down expired environment {} ({} containers)",
+                        expiration.environment,
+                        expiration.containers.len()
+                    );
+                }
+            }
+            Err(err) => {
+                warn!(state.logger, "Environment expiry failed: {}", err);
+            }
+        }
+    }
+}
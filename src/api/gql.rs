@@ -1,13 +1,154 @@
-use juniper::{EmptySubscription, FieldResult, IntoFieldError, RootNode};
-use slog::info;
+use chrono::{DateTime, Utc};
+use futures::{future, stream, Stream, TryStreamExt};
+use juniper::{DefaultScalarValue, FieldError, FieldResult, IntoFieldError, RootNode};
+use slog::warn;
+use std::pin::Pin;
+use std::time::Duration;
 
 use super::containers;
+use super::environments;
+use super::events;
+use super::exec;
+use super::expiry;
+use super::images;
+use super::logs;
+use super::reconcile;
+use super::registries;
+use super::spec;
+use super::stats;
+use super::templates;
+use super::users;
+use super::volumes;
+use crate::auth::{Principal, Role};
+use crate::error;
+use crate::state::pulls::PullProgress;
 use crate::state::State;
 
+/// How often the token of a long lived connection, such as a websocket, is checked.
+const TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The token of an 'Authorization: Bearer <token>' header value.
+/// Any other value is rejected, rather than treating the request as anonymous.
+pub fn bearer_token(authorization: &str) -> Result<String, error::Error> {
+    authorization
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(String::from)
+        .ok_or_else(|| error::Error::Unauthenticated {
+            msg: String::from("Malformed authorization, expected 'Bearer <token>'"),
+        })
+}
+
 #[derive(Debug, Clone)]
 pub struct Context {
     pub state: State,
     pub token: Option<String>,
+    /// The authenticated user, if the request carries a token
+    pub principal: Option<Principal>,
+}
+
+impl Context {
+    /// Authenticate a request with its bearer token.
+    /// Requests without token are anonymous, while requests with an invalid, expired, or
+    /// revoked token are rejected.
+    pub async fn new(state: State, token: Option<String>) -> Result<Self, error::Error> {
+        let principal = match token.as_deref() {
+            Some(token) => {
+                let claims =
+                    state
+                        .jwt
+                        .decode(token)
+                        .map_err(|err| error::Error::Unauthenticated {
+                            msg: format!("Invalid token: {}", err),
+                        })?;
+                let principal = Principal::from(claims);
+                if let Some(token_id) = principal.token_id.as_deref() {
+                    if users::is_token_revoked(token_id, &state).await? {
+                        return Err(error::Error::Unauthenticated {
+                            msg: String::from("Invalid token: Token revoked"),
+                        });
+                    }
+                }
+                Some(principal)
+            }
+            None => None,
+        };
+
+        Ok(Context {
+            state,
+            token,
+            principal,
+        })
+    }
+
+    /// Check that the token of the request is still valid, ie neither expired nor revoked.
+    /// This matters for long lived connections, which outlive their token.
+    pub async fn check_token(&self) -> Result<(), error::Error> {
+        let principal = match self.principal.as_ref() {
+            Some(principal) => principal,
+            None => return Ok(()),
+        };
+
+        if principal
+            .expires_at
+            .map_or(false, |expires_at| expires_at <= Utc::now())
+        {
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Token expired"),
+            });
+        }
+
+        if let Some(token_id) = principal.token_id.as_deref() {
+            if users::is_token_revoked(token_id, &self.state).await? {
+                return Err(error::Error::Unauthenticated {
+                    msg: String::from("Token revoked"),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves once the token of the request is no longer valid, so that long lived
+    /// connections can be closed. Anonymous requests are never invalidated.
+    pub async fn invalidated(&self) -> error::Error {
+        if self.principal.is_none() {
+            return future::pending().await;
+        }
+
+        let mut interval = tokio::time::interval(TOKEN_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.check_token().await {
+                Ok(()) => {}
+                Err(err @ error::Error::Unauthenticated { .. }) => return err,
+                Err(err) => warn!(self.state.logger, "Could not check token: {}", err),
+            }
+        }
+    }
+
+    /// The authenticated user making the request, which is required for most operations.
+    pub fn principal(&self) -> Result<&Principal, error::Error> {
+        self.principal
+            .as_ref()
+            .ok_or_else(|| error::Error::Unauthenticated {
+                msg: String::from("This operation requires an authenticated user"),
+            })
+    }
+
+    /// The guard of the operations: the authenticated user making the request, provided
+    /// they have the given role.
+    pub fn require(&self, role: Role) -> Result<&Principal, error::Error> {
+        let principal = self.principal()?;
+        if principal.has_role(role) {
+            Ok(principal)
+        } else {
+            Err(error::Error::Forbidden {
+                msg: format!("This operation requires the {} role", role.as_str()),
+            })
+        }
+    }
 }
 
 impl juniper::Context for Context {}
@@ -23,15 +164,197 @@ impl Query {
         &self,
         context: &Context,
     ) -> FieldResult<containers::MultiContainersResponseBody> {
-        if let Some(token) = &context.token {
-            info!(context.state.logger, "auth token: {}", token);
-        }
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
         containers::list_containers(context)
             .await
             .map_err(IntoFieldError::into_field_error)
             .into()
     }
 
+    /// Returns a list of environments
+    async fn environments(
+        &self,
+        context: &Context,
+    ) -> FieldResult<environments::MultiEnvironmentsResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::list_environments(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find an environment by name
+    async fn find_environment_by_name(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::find_environment_by_name(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the users and teams an environment is shared with
+    async fn environment_shares(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<environments::MultiSharesResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::list_environment_shares(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the environments torn down because they expired
+    async fn expirations(
+        &self,
+        context: &Context,
+    ) -> FieldResult<expiry::MultiExpirationsResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        expiry::list_expirations(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns a list of environment templates
+    async fn templates(
+        &self,
+        context: &Context,
+    ) -> FieldResult<templates::MultiTemplatesResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::list_templates(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find an environment template by name
+    async fn find_template_by_name(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<templates::SingleTemplateResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::find_template_by_name(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns a list of volumes
+    async fn volumes(&self, context: &Context) -> FieldResult<volumes::MultiVolumesResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        volumes::list_volumes(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the images stored on the docker host
+    async fn images(&self, context: &Context) -> FieldResult<images::MultiImagesResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        images::list_images(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the private registries we hold credentials for (admins only)
+    async fn registries(
+        &self,
+        context: &Context,
+    ) -> FieldResult<registries::MultiRegistriesResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        registries::list_registries(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the logs of a container.
+    /// tail is the number of lines to return from the end of the logs (default all),
+    /// and since the time from which logs are returned.
+    async fn container_logs(
+        &self,
+        name: String,
+        tail: Option<i32>,
+        since: Option<DateTime<Utc>>,
+        timestamps: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<logs::ContainerLogsResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        logs::container_logs(&name, tail, since, timestamps, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the current resource usage of a container
+    async fn container_stats(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<stats::ContainerStats> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        stats::container_stats(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns the differences between the containers recorded in the database
+    /// and the ones in the docker engine.
+    async fn drift(&self, context: &Context) -> FieldResult<reconcile::DriftResponseBody> {
+        context
+            .require(Role::Viewer)
+            .map_err(IntoFieldError::into_field_error)?;
+        reconcile::detect_drift(&context.state)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Returns a list of users
+    async fn users(&self, context: &Context) -> FieldResult<users::MultiUsersResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        users::list_users(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Find a user by username
+    async fn find_user_by_username(
+        &self,
+        username: String,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        users::find_user_by_username(context, &username)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
     // /// Find a container by name
     // async fn findContainerByName(
     //     &self,
@@ -50,11 +373,93 @@ pub struct Mutation;
     Context = Context
 )]
 impl Mutation {
+    /// Registers a new user
+    async fn register(
+        &self,
+        user: users::UserRequestBody,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        users::register_user(user, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Logs a user in, and returns a token to authenticate the following requests, and a
+    /// token to refresh it
+    async fn login(
+        &self,
+        credentials: users::CredentialsRequestBody,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        users::login_user(credentials, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Exchanges a refresh token for a new access token, and a new refresh token
+    async fn refresh(
+        &self,
+        refresh_token: String,
+        context: &Context,
+    ) -> FieldResult<users::AuthenticatedUserResponseBody> {
+        users::refresh_token(&refresh_token, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Logs the user out, revoking its access token, and the given refresh token, or all
+    /// its refresh tokens
+    async fn logout(
+        &self,
+        refresh_token: Option<String>,
+        context: &Context,
+    ) -> FieldResult<users::LogoutResponseBody> {
+        context
+            .principal()
+            .map_err(IntoFieldError::into_field_error)?;
+        users::logout_user(refresh_token, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Replaces the roles of a user (admins only)
+    async fn set_user_roles(
+        &self,
+        username: String,
+        roles: Vec<Role>,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        users::set_user_roles(&username, &roles, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Replaces the teams a user belongs to (admins only)
+    async fn set_user_teams(
+        &self,
+        username: String,
+        teams: Vec<String>,
+        context: &Context,
+    ) -> FieldResult<users::SingleUserResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        users::set_user_teams(&username, &teams, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
     async fn create_container(
         &self,
         container: containers::ContainerRequestBody,
         context: &Context,
     ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
         containers::create_container(container, context)
             .await
             .map_err(IntoFieldError::into_field_error)
@@ -65,14 +470,490 @@ impl Mutation {
         name: String,
         context: &Context,
     ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
         containers::delete_container(&name, context)
             .await
             .map_err(IntoFieldError::into_field_error)
     }
+
+    /// Creates an environment and all its containers
+    async fn create_environment(
+        &self,
+        environment: environments::EnvironmentRequestBody,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::create_environment(environment, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Creates an environment from a compose-like YAML spec.
+    /// The environment belongs to the user, unless an admin gives another owner.
+    async fn create_environment_from_spec(
+        &self,
+        yaml: String,
+        owner: Option<String>,
+        ttl: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        spec::create_environment_from_spec(&yaml, owner, ttl, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Stores a new environment template
+    async fn create_template(
+        &self,
+        template: templates::TemplateRequestBody,
+        context: &Context,
+    ) -> FieldResult<templates::SingleTemplateResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::create_template(template, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Updates an existing environment template
+    async fn update_template(
+        &self,
+        template: templates::TemplateRequestBody,
+        context: &Context,
+    ) -> FieldResult<templates::SingleTemplateResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::update_template(template, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deletes an environment template
+    async fn delete_template(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<templates::SingleTemplateResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::delete_template(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Creates an environment from a template, given values for its parameters.
+    /// The environment belongs to the user, unless an admin gives another owner.
+    async fn instantiate_template(
+        &self,
+        template: String,
+        params: Vec<templates::ParameterRequestBody>,
+        owner: Option<String>,
+        ttl: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        templates::instantiate_template(&template, params, owner, ttl, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Pushes back the expiry of an environment by the given number of seconds
+    async fn extend_environment(
+        &self,
+        name: String,
+        seconds: i32,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::extend_environment(&name, seconds, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Starts again the containers of an environment stopped for being idle
+    async fn start_environment(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::start_environment(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Shares an environment with a user, or a team (owner or admins only)
+    async fn share_environment(
+        &self,
+        name: String,
+        share: environments::ShareRequestBody,
+        context: &Context,
+    ) -> FieldResult<environments::MultiSharesResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::share_environment(&name, share, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Stops sharing an environment with a user, or a team (owner or admins only)
+    async fn unshare_environment(
+        &self,
+        name: String,
+        share: environments::ShareRequestBody,
+        context: &Context,
+    ) -> FieldResult<environments::MultiSharesResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::unshare_environment(&name, share, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deletes an environment and all its containers
+    async fn delete_environment(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<environments::SingleEnvironmentResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        environments::delete_environment(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Creates a named volume
+    async fn create_volume(
+        &self,
+        volume: volumes::VolumeRequestBody,
+        context: &Context,
+    ) -> FieldResult<volumes::SingleVolumeResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        volumes::create_volume(volume, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deletes a volume which is no longer mounted
+    async fn delete_volume(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<volumes::SingleVolumeResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        volumes::delete_volume(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deletes all the volumes not mounted by any container
+    async fn prune_volumes(
+        &self,
+        context: &Context,
+    ) -> FieldResult<volumes::PruneVolumesResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        volumes::prune_volumes(context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Pulls an image. The progress is reported by the imagePullProgress subscription.
+    async fn pull_image(
+        &self,
+        image: String,
+        context: &Context,
+    ) -> FieldResult<images::SingleImageResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        images::pull_image(&image, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Removes an image. Unless forced, images used by a container are kept.
+    async fn remove_image(
+        &self,
+        image: String,
+        force: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<images::RemoveImageResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        images::remove_image(&image, force.unwrap_or(false), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Removes the images not used by any container.
+    /// Only dangling images are removed, unless all is set.
+    async fn prune_images(
+        &self,
+        all: Option<bool>,
+        context: &Context,
+    ) -> FieldResult<images::PruneImagesResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        images::prune_images(all.unwrap_or(false), context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Stores the credentials used to pull images from a private registry (admins only)
+    async fn register_registry_credentials(
+        &self,
+        credentials: registries::RegistryCredentialsRequestBody,
+        context: &Context,
+    ) -> FieldResult<registries::SingleRegistryResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        registries::register_registry_credentials(credentials, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Replaces the credentials of a private registry (admins only)
+    async fn rotate_registry_credentials(
+        &self,
+        credentials: registries::RegistryCredentialsRequestBody,
+        context: &Context,
+    ) -> FieldResult<registries::SingleRegistryResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        registries::rotate_registry_credentials(credentials, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Deletes the credentials of a private registry (admins only)
+    async fn delete_registry_credentials(
+        &self,
+        registry: String,
+        context: &Context,
+    ) -> FieldResult<registries::SingleRegistryResponseBody> {
+        context
+            .require(Role::Admin)
+            .map_err(IntoFieldError::into_field_error)?;
+        registries::delete_registry_credentials(&registry, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Starts a stopped container
+    async fn start_container(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        containers::start_container(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Stops a container, keeping its state so it can be started again.
+    /// The timeout is the number of seconds to wait before killing the container.
+    async fn stop_container(
+        &self,
+        name: String,
+        timeout: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        containers::stop_container(&name, timeout, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Restarts a container
+    async fn restart_container(
+        &self,
+        name: String,
+        timeout: Option<i32>,
+        context: &Context,
+    ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        containers::restart_container(&name, timeout, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Runs a command in a container, and returns its exit code and output
+    async fn exec_in_container(
+        &self,
+        name: String,
+        cmd: Vec<String>,
+        env: Option<Vec<containers::EnvVarRequestBody>>,
+        working_dir: Option<String>,
+        context: &Context,
+    ) -> FieldResult<exec::ExecResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        exec::exec_in_container(&name, cmd, env, working_dir, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Pauses a running container
+    async fn pause_container(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        containers::pause_container(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
+
+    /// Resumes a paused container
+    async fn unpause_container(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> FieldResult<containers::SingleContainerResponseBody> {
+        context
+            .require(Role::Developer)
+            .map_err(IntoFieldError::into_field_error)?;
+        containers::unpause_container(&name, context)
+            .await
+            .map_err(IntoFieldError::into_field_error)
+    }
 }
 
-type Schema = RootNode<'static, Query, Mutation, EmptySubscription<Context>>;
+type ContainerEventStream =
+    Pin<Box<dyn Stream<Item = Result<events::ContainerEvent, FieldError>> + Send>>;
+
+type LogLineStream = Pin<Box<dyn Stream<Item = Result<logs::LogLine, FieldError>> + Send>>;
+
+type ContainerStatsStream =
+    Pin<Box<dyn Stream<Item = Result<stats::ContainerStats, FieldError>> + Send>>;
+
+type PullProgressStream = Pin<Box<dyn Stream<Item = Result<PullProgress, FieldError>> + Send>>;
+
+pub struct Subscription;
+
+#[juniper::graphql_subscription(
+    Context = Context
+)]
+impl Subscription {
+    /// Streams the docker engine events (start, die, oom, ...) of the managed containers
+    /// the user can see
+    async fn container_events(&self, context: &Context) -> ContainerEventStream {
+        let principal = match context.require(Role::Viewer) {
+            Ok(principal) => principal,
+            Err(err) => return Box::pin(stream::once(future::err(err.into_field_error()))),
+        };
+        // Admins see the events of all the containers, the others those they can see.
+        let username = if principal.has_role(Role::Admin) {
+            None
+        } else {
+            Some(principal.username.clone())
+        };
+        Box::pin(
+            events::container_events(username, &context.state)
+                .map_err(IntoFieldError::into_field_error),
+        )
+    }
+
+    /// Streams the progress of the image pulls, optionally for a single image
+    async fn image_pull_progress(
+        &self,
+        image: Option<String>,
+        context: &Context,
+    ) -> PullProgressStream {
+        if let Err(err) = context.require(Role::Viewer) {
+            return Box::pin(stream::once(future::err(err.into_field_error())));
+        }
+        Box::pin(
+            images::pull_progress(image, &context.state).map_err(IntoFieldError::into_field_error),
+        )
+    }
+
+    /// Streams the logs of a container, as they are printed
+    async fn follow_container_logs(
+        &self,
+        name: String,
+        tail: Option<i32>,
+        since: Option<DateTime<Utc>>,
+        timestamps: Option<bool>,
+        context: &Context,
+    ) -> LogLineStream {
+        if let Err(err) = context.require(Role::Viewer) {
+            return Box::pin(stream::once(future::err(err.into_field_error())));
+        }
+        match logs::follow_container_logs(&name, tail, since, timestamps, context).await {
+            Ok(lines) => Box::pin(lines.map_err(IntoFieldError::into_field_error)),
+            Err(err) => Box::pin(stream::once(future::err(err.into_field_error()))),
+        }
+    }
+
+    /// Streams the resource usage of a container, every second
+    async fn follow_container_stats(
+        &self,
+        name: String,
+        context: &Context,
+    ) -> ContainerStatsStream {
+        if let Err(err) = context.require(Role::Viewer) {
+            return Box::pin(stream::once(future::err(err.into_field_error())));
+        }
+        match stats::follow_container_stats(&name, context).await {
+            Ok(stats) => Box::pin(stats.map_err(IntoFieldError::into_field_error)),
+            Err(err) => Box::pin(stream::once(future::err(err.into_field_error()))),
+        }
+    }
+}
+
+pub type Schema = RootNode<'static, Query, Mutation, Subscription>;
+
+pub type Coordinator = juniper_subscriptions::Coordinator<
+    'static,
+    Query,
+    Mutation,
+    Subscription,
+    Context,
+    DefaultScalarValue,
+>;
 
 pub fn schema() -> Schema {
-    Schema::new(Query, Mutation, EmptySubscription::new())
+    Schema::new(Query, Mutation, Subscription)
 }
@@ -0,0 +1,180 @@
+use chrono::Utc;
+use futures::TryFutureExt;
+use slog::{info, warn};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::api::environments::{load_environment, stop_environment, touch_environment};
+use crate::api::model::*;
+use crate::api::stats::read_stats;
+use crate::db::model::ProvideData;
+use crate::db::Db;
+use crate::error;
+use crate::settings;
+use crate::state::State;
+
+/// Check the activity of the environments which are not stopped, and stop the ones which
+/// have been idle for longer than the configured timeout.
+/// `traffic` holds the network bytes of each container at the previous check, and is
+/// updated with the current ones. Returns the names of the stopped environments.
+pub async fn stop_idle_environments(
+    state: &State,
+    settings: &settings::Idle,
+    traffic: &mut HashMap<String, f64>,
+) -> Result<Vec<String>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entities = tx
+        .get_all_environments()
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get all them environments",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let timeout = chrono::Duration::seconds(settings.timeout as i64);
+    let mut current = HashMap::new();
+    let mut stopped = Vec::new();
+
+    for entity in entities
+        .into_iter()
+        .filter(|entity| entity.stopped_at.is_none())
+    {
+        // One environment failing to be checked, or stopped, must not spare the others:
+        // it is logged, and checked again at the next run.
+        let (entity, containers) = match load_environment(&entity.name, state).await {
+            Ok(Some(environment)) => environment,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!(
+                    state.logger,
+                    "Could not load environment {}: {}", entity.name, err
+                );
+                continue;
+            }
+        };
+
+        let mut active = false;
+        for container in containers
+            .iter()
+            .filter(|container| container.state == ContainerState::Running.as_str())
+        {
+            let stats = match read_stats(&container.name, state).await {
+                Ok(stats) => stats,
+                Err(err) => {
+                    // We don't stop an environment we cannot observe.
+                    warn!(
+                        state.logger,
+                        "Could not read stats of container {}: {}", container.name, err
+                    );
+                    active = true;
+                    continue;
+                }
+            };
+
+            let bytes = stats.network_rx + stats.network_tx;
+
+            let network_active =
+                is_network_active(traffic.get(&container.name), bytes, settings.network_bytes);
+
+            if network_active || stats.cpu_percent > settings.cpu_percent {
+                active = true;
+            }
+
+            current.insert(container.name.clone(), bytes);
+        }
+
+        if active {
+            if let Err(err) = touch_environment(entity.id, state).await {
+                warn!(
+                    state.logger,
+                    "Could not record activity of environment {}: {}", entity.name, err
+                );
+            }
+        } else if Utc::now() - entity.active_at > timeout {
+            info!(
+                state.logger,
+                "Environment {} idle since {}", entity.name, entity.active_at
+            );
+            match stop_environment(&entity.name, state).await {
+                Ok(Some(_)) => stopped.push(entity.name),
+                Ok(None) => {}
+                Err(err) => warn!(
+                    state.logger,
+                    "Could not stop idle environment {}: {}", entity.name, err
+                ),
+            }
+        }
+    }
+
+    *traffic = current;
+
+    Ok(stopped)
+}
+
+/// Whether a container sent or received more than `threshold` bytes since the previous
+/// check.
+/// Without a previous check, the network activity is unknown, so we assume there was
+/// some. So we do when the counters went down, which happens when the container was
+/// restarted in between.
+fn is_network_active(previous: Option<&f64>, bytes: f64, threshold: f64) -> bool {
+    match previous {
+        Some(previous) => bytes < *previous || bytes - previous > threshold,
+        None => true,
+    }
+}
+
+/// Periodically stop the idle environments.
+/// This is meant to be spawned in the background when the server starts.
+pub async fn run_idle(state: State, settings: settings::Idle) {
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.interval));
+    let mut traffic = HashMap::new();
+    loop {
+        interval.tick().await;
+        match stop_idle_environments(&state, &settings, &mut traffic).await {
+            Ok(stopped) => {
+                for name in stopped {
+                    info!(state.logger, "Stopped idle environment {}", name);
+                }
+            }
+            Err(err) => {
+                warn!(state.logger, "Idle environment detection failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_is_active_without_previous_check() {
+        assert!(is_network_active(None, 0.0, 1000.0));
+    }
+
+    #[test]
+    fn network_is_active_above_threshold() {
+        assert!(is_network_active(Some(&1000.0), 3000.0, 1000.0));
+        assert!(!is_network_active(Some(&1000.0), 1500.0, 1000.0));
+        assert!(!is_network_active(Some(&1000.0), 1000.0, 1000.0));
+    }
+
+    #[test]
+    fn network_is_active_when_counters_reset() {
+        assert!(is_network_active(Some(&5000.0), 10.0, 1000.0));
+    }
+}
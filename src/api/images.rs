@@ -0,0 +1,298 @@
+use bollard::image::{
+    CreateImageOptions, ListImagesOptions, PruneImagesOptions, RemoveImageOptions,
+};
+use bollard::service::ImageSummary;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{stream, Stream, TryStreamExt};
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use slog::{info, warn};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::default::Default;
+use tokio::sync::broadcast;
+
+use crate::api::gql::Context;
+use crate::api::policy::check_image;
+use crate::api::registries::credentials_for_image;
+use crate::error;
+use crate::state::pulls::PullProgress;
+use crate::state::State;
+
+/// An image stored on the docker host
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Image {
+    pub id: String,
+    pub tags: Vec<String>,
+    pub digests: Vec<String>,
+    /// Size in bytes
+    pub size: f64,
+    /// Number of containers using the image, if known
+    pub containers: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ImageSummary> for Image {
+    fn from(image: ImageSummary) -> Self {
+        Image {
+            id: image.id,
+            tags: image.repo_tags,
+            digests: image.repo_digests,
+            size: image.size as f64,
+            // The engine reports -1 when it did not count the containers.
+            containers: i32::try_from(image.containers)
+                .ok()
+                .filter(|containers| *containers >= 0),
+            created_at: DateTime::<Utc>::from_utc(
+                NaiveDateTime::from_timestamp(image.created, 0),
+                Utc,
+            ),
+        }
+    }
+}
+
+/// The response body for single image
+/// It is optional, since we may be looking for an image which
+/// does not match the query criteria.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleImageResponseBody {
+    pub image: Option<Image>,
+}
+
+/// The response body for multiple images
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiImagesResponseBody {
+    pub images: Vec<Image>,
+    pub images_count: i32,
+}
+
+impl From<Vec<Image>> for MultiImagesResponseBody {
+    fn from(images: Vec<Image>) -> Self {
+        let images_count = i32::try_from(images.len()).unwrap();
+        Self {
+            images,
+            images_count,
+        }
+    }
+}
+
+/// The response body for an image removal
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveImageResponseBody {
+    /// The tags removed from the image
+    pub untagged: Vec<String>,
+    /// The ids of the deleted image layers
+    pub deleted: Vec<String>,
+}
+
+/// The response body for an image pruning
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneImagesResponseBody {
+    /// The ids of the deleted images
+    pub deleted: Vec<String>,
+    /// Disk space reclaimed, in bytes
+    pub space_reclaimed: f64,
+}
+
+/// Retrieve the images stored on the docker host
+pub async fn list_images(context: &Context) -> Result<MultiImagesResponseBody, error::Error> {
+    async move {
+        let images = list_images_by(HashMap::new(), &context.state).await?;
+
+        Ok(MultiImagesResponseBody::from(images))
+    }
+    .await
+}
+
+/// Pull an image, so that containers using it start without delay.
+/// The image must be allowed by the image policy.
+pub async fn pull_image(
+    image: &str,
+    context: &Context,
+) -> Result<SingleImageResponseBody, error::Error> {
+    async move {
+        let image = check_image(image, &context.state.policy, &context.state.logger)?;
+
+        pull(&image, &context.state).await?;
+
+        let mut filters = HashMap::new();
+        filters.insert("reference", vec![image.as_str()]);
+
+        let images = list_images_by(filters, &context.state).await?;
+
+        Ok(SingleImageResponseBody {
+            image: images.into_iter().next(),
+        })
+    }
+    .await
+}
+
+/// Remove an image from the docker host.
+/// Unless forced, images used by a container cannot be removed.
+pub async fn remove_image(
+    image: &str,
+    force: bool,
+    context: &Context,
+) -> Result<RemoveImageResponseBody, error::Error> {
+    async move {
+        info!(context.state.logger, "Removing image {}", image);
+
+        let options = Some(RemoveImageOptions {
+            force,
+            ..Default::default()
+        });
+
+        let items = context
+            .state
+            .docker
+            .remove_image(image, options, None)
+            .await
+            .context(error::BollardError {
+                msg: "Could not remove image",
+            })?;
+
+        let mut untagged = Vec::new();
+        let mut deleted = Vec::new();
+        for item in items {
+            untagged.extend(item.untagged);
+            deleted.extend(item.deleted);
+        }
+
+        Ok(RemoveImageResponseBody { untagged, deleted })
+    }
+    .await
+}
+
+/// Remove the images not used by any container.
+/// By default only dangling images (without tag) are removed.
+pub async fn prune_images(
+    all: bool,
+    context: &Context,
+) -> Result<PruneImagesResponseBody, error::Error> {
+    async move {
+        let mut filters = HashMap::new();
+        filters.insert("dangling", vec![if all { "false" } else { "true" }]);
+
+        let resp = context
+            .state
+            .docker
+            .prune_images(Some(PruneImagesOptions { filters }))
+            .await
+            .context(error::BollardError {
+                msg: "Could not prune images",
+            })?;
+
+        let deleted = resp
+            .images_deleted
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|item| item.deleted)
+            .collect::<Vec<_>>();
+
+        info!(context.state.logger, "Pruned {} images", deleted.len());
+
+        Ok(PruneImagesResponseBody {
+            deleted,
+            space_reclaimed: resp.space_reclaimed.unwrap_or_default() as f64,
+        })
+    }
+    .await
+}
+
+/// Pull an image, broadcasting the progress reported by the docker engine.
+pub async fn pull(image: &str, state: &State) -> Result<(), error::Error> {
+    info!(state.logger, "Pulling image {}", image);
+
+    let options = Some(CreateImageOptions {
+        from_image: image,
+        ..Default::default()
+    });
+
+    let credentials = credentials_for_image(image, state).await?;
+
+    // Sending fails when nobody is subscribed, which is fine.
+    // The docker engine reports some failures, eg a missing tag or a denied access,
+    // in the progress stream rather than with an error status.
+    let res = state
+        .docker
+        .create_image(options, None, credentials)
+        .map_err(|source| error::Error::BollardError {
+            msg: String::from("Could not pull image"),
+            source,
+        })
+        .try_for_each(|info| {
+            let error = info.error.clone();
+            let _ = state.pulls.send(PullProgress::new(image, info));
+            futures::future::ready(match error {
+                Some(error) => Err(error::Error::MiscError {
+                    msg: format!("Could not pull image {}: {}", image, error),
+                }),
+                None => Ok(()),
+            })
+        })
+        .await;
+
+    let error = res.as_ref().err().map(|err| err.to_string());
+    let _ = state.pulls.send(PullProgress::done(image, error));
+
+    res
+}
+
+/// Follow the progress of the image pulls, optionally restricted to a single image.
+pub fn pull_progress(
+    image: Option<String>,
+    state: &State,
+) -> impl Stream<Item = Result<PullProgress, error::Error>> + Send + 'static {
+    let receiver = state.pulls.subscribe();
+    let logger = state.logger.clone();
+
+    stream::unfold(receiver, move |mut receiver| {
+        let image = image.clone();
+        let logger = logger.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(progress) => {
+                        if image
+                            .as_ref()
+                            .map_or(true, |image| *image == progress.image)
+                        {
+                            return Some((Ok(progress), receiver));
+                        }
+                    }
+                    Err(broadcast::RecvError::Lagged(skipped)) => {
+                        warn!(
+                            logger,
+                            "Pull progress subscriber skipped {} messages", skipped
+                        );
+                    }
+                    Err(broadcast::RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
+
+async fn list_images_by(
+    filters: HashMap<&str, Vec<&str>>,
+    state: &State,
+) -> Result<Vec<Image>, error::Error> {
+    let images = state
+        .docker
+        .list_images(Some(ListImagesOptions {
+            filters,
+            ..Default::default()
+        }))
+        .await
+        .context(error::BollardError {
+            msg: "Could not list images",
+        })?;
+
+    Ok(images.into_iter().map(Image::from).collect())
+}
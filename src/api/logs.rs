@@ -0,0 +1,132 @@
+use bollard::container::{LogOutput, LogsOptions};
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use juniper::{GraphQLEnum, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::convert::TryFrom;
+
+use crate::api::containers::find_managed_container;
+use crate::api::gql::Context;
+use crate::error;
+
+/// The stream a log line was written to
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, GraphQLEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    Stdin,
+    Console,
+}
+
+/// A line printed by a container
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub message: String,
+}
+
+impl From<LogOutput> for LogLine {
+    fn from(output: LogOutput) -> Self {
+        let stream = match output {
+            LogOutput::StdOut { .. } => LogStream::Stdout,
+            LogOutput::StdErr { .. } => LogStream::Stderr,
+            LogOutput::StdIn { .. } => LogStream::Stdin,
+            LogOutput::Console { .. } => LogStream::Console,
+        };
+        LogLine {
+            stream,
+            message: output.to_string(),
+        }
+    }
+}
+
+/// The response body for container logs
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerLogsResponseBody {
+    pub lines: Vec<LogLine>,
+    pub lines_count: i32,
+}
+
+impl From<Vec<LogLine>> for ContainerLogsResponseBody {
+    fn from(lines: Vec<LogLine>) -> Self {
+        let lines_count = i32::try_from(lines.len()).unwrap();
+        Self { lines, lines_count }
+    }
+}
+
+/// Build the docker engine options for retrieving logs.
+/// When no tail is given, all the lines are returned.
+fn logs_options(
+    follow: bool,
+    tail: Option<i32>,
+    since: Option<DateTime<Utc>>,
+    timestamps: Option<bool>,
+) -> LogsOptions<String> {
+    LogsOptions {
+        follow,
+        stdout: true,
+        stderr: true,
+        since: since.map(|since| since.timestamp()).unwrap_or(0),
+        timestamps: timestamps.unwrap_or(false),
+        tail: tail
+            .map(|tail| tail.to_string())
+            .unwrap_or_else(|| String::from("all")),
+        ..Default::default()
+    }
+}
+
+/// Retrieve the logs of a managed container
+pub async fn container_logs(
+    name: &str,
+    tail: Option<i32>,
+    since: Option<DateTime<Utc>>,
+    timestamps: Option<bool>,
+    context: &Context,
+) -> Result<ContainerLogsResponseBody, error::Error> {
+    async move {
+        find_managed_container(name, context).await?;
+
+        let options = Some(logs_options(false, tail, since, timestamps));
+
+        let lines = context
+            .state
+            .docker
+            .logs(name, options)
+            .map_ok(LogLine::from)
+            .try_collect::<Vec<_>>()
+            .await
+            .context(error::BollardError {
+                msg: "Could not retrieve container logs",
+            })?;
+
+        Ok(ContainerLogsResponseBody::from(lines))
+    }
+    .await
+}
+
+/// Follow the logs of a managed container, as they are printed.
+pub async fn follow_container_logs(
+    name: &str,
+    tail: Option<i32>,
+    since: Option<DateTime<Utc>>,
+    timestamps: Option<bool>,
+    context: &Context,
+) -> Result<impl Stream<Item = Result<LogLine, error::Error>> + Send + 'static, error::Error> {
+    find_managed_container(name, context).await?;
+
+    let options = Some(logs_options(true, tail, since, timestamps));
+
+    Ok(context
+        .state
+        .docker
+        .logs(name, options)
+        .map_ok(LogLine::from)
+        .map_err(|err| error::Error::BollardError {
+            msg: String::from("Could not follow container logs"),
+            source: err,
+        }))
+}
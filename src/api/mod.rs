@@ -1,4 +1,24 @@
 pub mod client;
 pub mod containers;
+pub mod engine;
+pub mod environments;
+pub mod events;
+pub mod exec;
+pub mod expiry;
 pub mod gql;
+pub mod idle;
+pub mod images;
+pub mod logs;
 pub mod model;
+pub mod networks;
+pub mod policy;
+pub mod reconcile;
+pub mod reference;
+pub mod registries;
+pub mod spec;
+pub mod stats;
+pub mod subscriptions;
+pub mod templates;
+pub mod terminal;
+pub mod users;
+pub mod volumes;
@@ -1,7 +1,10 @@
-use chrono::{DateTime, Utc};
-use juniper::GraphQLObject;
+use bollard::service::ContainerSummaryInner;
+use chrono::{DateTime, Datelike, Utc};
+use juniper::{GraphQLEnum, GraphQLObject};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
+use crate::api::engine::InspectState;
 use crate::db::model::*;
 
 /// A container
@@ -11,27 +14,576 @@ pub struct Container {
     pub id: String,
     pub name: String,
     pub image: String,
+    /// The user who created the container
+    pub owner: Option<String>,
+    pub env: Vec<EnvVar>,
+    pub cmd: Vec<String>,
+    pub entrypoint: Vec<String>,
+    pub ports: Vec<PortBinding>,
+    pub mounts: Vec<Mount>,
+    pub working_dir: Option<String>,
+    pub labels: Vec<Label>,
+    pub state: ContainerState,
+    pub status: Option<String>,
+    pub exit_code: Option<i32>,
+    pub health: Option<HealthStatus>,
+    pub published_ports: Vec<PublishedPort>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl Container {
+    /// Update the container with the information from the docker engine's container list.
+    pub fn with_summary(mut self, summary: ContainerSummaryInner) -> Self {
+        if let Some(state) = summary
+            .state
+            .as_deref()
+            .and_then(|state| ContainerState::from_str(state).ok())
+        {
+            self.state = state;
+        }
+        self.status = summary.status;
+        self.published_ports = summary
+            .ports
+            .unwrap_or_default()
+            .into_iter()
+            .map(|port| PublishedPort {
+                ip: port.ip,
+                private_port: port.private_port as i32,
+                public_port: port.public_port.map(|port| port as i32),
+                protocol: port.typ.to_string(),
+            })
+            .collect();
+        if let Some(labels) = summary.labels {
+            self.labels = labels
+                .into_iter()
+                .map(|(key, value)| Label { key, value })
+                .collect();
+        }
+        self
+    }
+
+    /// Update the container with the information from the docker engine's container inspection.
+    pub fn with_inspect(mut self, inspect: InspectState) -> Self {
+        self.exit_code = inspect.exit_code.map(|code| code as i32);
+        self.health = inspect.health;
+        self.started_at = inspect.started_at.as_deref().and_then(parse_timestamp);
+        self.finished_at = inspect.finished_at.as_deref().and_then(parse_timestamp);
+        self
+    }
+}
+
+/// Parse a timestamp returned by the docker engine.
+/// The engine uses '0001-01-01T00:00:00Z' for events that did not happen yet (eg the
+/// finish time of a running container), so we treat it as missing.
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .filter(|timestamp| timestamp.year() > 1)
+}
+
 impl From<ContainerEntity> for Container {
     fn from(entity: ContainerEntity) -> Self {
         let ContainerEntity {
             id,
             name,
             image,
+            spec,
+            state,
+            owner,
             created_at,
             updated_at,
             ..
         } = entity;
 
+        let ContainerSpec {
+            env,
+            cmd,
+            entrypoint,
+            ports,
+            mounts,
+            working_dir,
+            labels,
+        } = spec;
+
         Container {
             id,
             name,
             image,
+            owner,
+            env: env.iter().map(|env| EnvVar::from(env.as_str())).collect(),
+            cmd,
+            entrypoint,
+            ports: ports
+                .iter()
+                .filter_map(|port| PortBinding::parse(port))
+                .collect(),
+            mounts: mounts
+                .iter()
+                .filter_map(|mount| Mount::parse(mount))
+                .collect(),
+            working_dir,
+            labels: labels
+                .iter()
+                .map(|label| Label::from(label.as_str()))
+                .collect(),
+            state: ContainerState::from_str(&state).unwrap_or(ContainerState::Created),
+            status: None,
+            exit_code: None,
+            health: None,
+            published_ports: vec![],
+            started_at: None,
+            finished_at: None,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// A group of containers, created and deleted as a unit
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Environment {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub owner: String,
+    /// The docker network shared by the containers
+    pub network: Option<String>,
+    pub containers: Vec<Container>,
+    /// When the environment is torn down automatically
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The last time the environment was accessed, or its containers were active
+    pub active_at: DateTime<Utc>,
+    /// When the environment was stopped for being idle
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<(EnvironmentEntity, Vec<ContainerEntity>)> for Environment {
+    fn from((entity, containers): (EnvironmentEntity, Vec<ContainerEntity>)) -> Self {
+        let EnvironmentEntity {
+            id,
+            name,
+            description,
+            owner,
+            network,
+            expires_at,
+            active_at,
+            stopped_at,
+            created_at,
+            updated_at,
+        } = entity;
+
+        Environment {
+            id: id.to_string(),
+            name,
+            description,
+            owner,
+            network,
+            containers: containers.into_iter().map(Container::from).collect(),
+            expires_at,
+            active_at,
+            stopped_at,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// Whether an environment is shared with a single user, or with the members of a team
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, GraphQLEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareKind {
+    User,
+    Team,
+}
+
+impl ShareKind {
+    /// The name used in the database for this kind of share
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareKind::User => "user",
+            ShareKind::Team => "team",
+        }
+    }
+}
+
+impl FromStr for ShareKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "user" => Ok(ShareKind::User),
+            "team" => Ok(ShareKind::Team),
+            _ => Err(format!("Unknown share kind '{}'", kind)),
+        }
+    }
+}
+
+/// An environment shared with a user, or a team
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub environment_id: String,
+    pub kind: ShareKind,
+    /// The username, or the team name
+    pub grantee: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ShareEntity> for Share {
+    fn from(entity: ShareEntity) -> Self {
+        let ShareEntity {
+            environment_id,
+            kind,
+            grantee,
+            created_at,
+        } = entity;
+
+        Share {
+            environment_id: environment_id.to_string(),
+            kind: ShareKind::from_str(&kind).unwrap_or(ShareKind::User),
+            grantee,
+            created_at,
+        }
+    }
+}
+
+/// An environment torn down because it expired
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Expiration {
+    pub id: String,
+    pub environment: String,
+    pub owner: String,
+    /// The names of the containers removed with the environment
+    pub containers: Vec<String>,
+    pub expired_at: DateTime<Utc>,
+    pub removed_at: DateTime<Utc>,
+}
+
+impl From<ExpirationEntity> for Expiration {
+    fn from(entity: ExpirationEntity) -> Self {
+        let ExpirationEntity {
+            id,
+            environment,
+            owner,
+            containers,
+            expired_at,
+            removed_at,
+        } = entity;
+
+        Expiration {
+            id: id.to_string(),
+            environment,
+            owner,
+            containers,
+            expired_at,
+            removed_at,
+        }
+    }
+}
+
+/// A reusable environment spec, with parameter placeholders
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// The compose-like YAML spec, with '${NAME}' or '${NAME:-default}' placeholders
+    pub spec: String,
+    /// The names of the placeholders found in the spec
+    pub parameters: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<TemplateEntity> for Template {
+    fn from(entity: TemplateEntity) -> Self {
+        let TemplateEntity {
+            id,
+            name,
+            description,
+            spec,
+            parameters,
             created_at,
             updated_at,
+        } = entity;
+
+        Template {
+            id: id.to_string(),
+            name,
+            description,
+            spec,
+            parameters,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// A named volume, whose data survives the containers mounting it
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Volume {
+    pub name: String,
+    pub driver: String,
+    /// Where the volume is stored on the docker host.
+    /// Missing if the volume no longer exists in the docker engine.
+    pub mountpoint: Option<String>,
+    /// The environment the volume is attached to
+    pub environment_id: Option<String>,
+    /// The user who owns the volume
+    pub owner: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<VolumeEntity> for Volume {
+    fn from(entity: VolumeEntity) -> Self {
+        let VolumeEntity {
+            name,
+            driver,
+            environment_id,
+            owner,
+            created_at,
+            updated_at,
+        } = entity;
+
+        Volume {
+            name,
+            driver,
+            mountpoint: None,
+            environment_id: environment_id.map(|id| id.to_string()),
+            owner,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// A registered user. The password hash is never exposed.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    /// The teams the user belongs to, with which environments can be shared
+    pub teams: Vec<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<UserEntity> for User {
+    fn from(entity: UserEntity) -> Self {
+        let UserEntity {
+            id,
+            username,
+            email,
+            roles,
+            teams,
+            active,
+            created_at,
+            updated_at,
+            ..
+        } = entity;
+
+        User {
+            id: id.to_string(),
+            username,
+            email,
+            roles,
+            teams,
+            active,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// The state of a container, as reported by the docker engine
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, GraphQLEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerState {
+    Created,
+    Restarting,
+    Running,
+    Removing,
+    Paused,
+    Exited,
+    Dead,
+    /// The container is recorded in the database, but no longer exists in the docker engine
+    Missing,
+}
+
+impl ContainerState {
+    /// The name used by the docker engine (and the database) for this state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContainerState::Created => "created",
+            ContainerState::Restarting => "restarting",
+            ContainerState::Running => "running",
+            ContainerState::Removing => "removing",
+            ContainerState::Paused => "paused",
+            ContainerState::Exited => "exited",
+            ContainerState::Dead => "dead",
+            ContainerState::Missing => "missing",
+        }
+    }
+}
+
+impl FromStr for ContainerState {
+    type Err = String;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "created" => Ok(ContainerState::Created),
+            "restarting" => Ok(ContainerState::Restarting),
+            "running" => Ok(ContainerState::Running),
+            "removing" => Ok(ContainerState::Removing),
+            "paused" => Ok(ContainerState::Paused),
+            "exited" => Ok(ContainerState::Exited),
+            "dead" => Ok(ContainerState::Dead),
+            "missing" => Ok(ContainerState::Missing),
+            _ => Err(format!("Unknown container state '{}'", state)),
+        }
+    }
+}
+
+/// The result of a container's health check
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, GraphQLEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+/// Parse the health status reported by the docker engine's inspection.
+/// Containers without health check report 'none', which is not a health.
+impl FromStr for HealthStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "starting" => Ok(HealthStatus::Starting),
+            "healthy" => Ok(HealthStatus::Healthy),
+            "unhealthy" => Ok(HealthStatus::Unhealthy),
+            _ => Err(format!("Unknown health status '{}'", status)),
+        }
+    }
+}
+
+/// A container port published on the docker host
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedPort {
+    pub ip: Option<String>,
+    pub private_port: i32,
+    pub public_port: Option<i32>,
+    pub protocol: String,
+}
+
+/// An environment variable, as given to the container
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+}
+
+impl From<&str> for EnvVar {
+    /// Builds an environment variable from its 'NAME=value' representation
+    fn from(env: &str) -> Self {
+        let mut parts = env.splitn(2, '=');
+        EnvVar {
+            name: String::from(parts.next().unwrap_or_default()),
+            value: String::from(parts.next().unwrap_or_default()),
+        }
+    }
+}
+
+/// A container port, possibly bound to a port on the docker host
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PortBinding {
+    pub container_port: i32,
+    pub host_port: Option<i32>,
+    pub protocol: String,
+}
+
+impl PortBinding {
+    /// Builds a port binding from its '[host_port:]container_port/protocol' representation
+    pub fn parse(port: &str) -> Option<Self> {
+        let (ports, protocol) = match port.find('/') {
+            Some(idx) => (&port[..idx], &port[idx + 1..]),
+            None => (port, "tcp"),
+        };
+        let (host_port, container_port) = match ports.find(':') {
+            Some(idx) => (Some(&ports[..idx]), &ports[idx + 1..]),
+            None => (None, ports),
+        };
+        let container_port = container_port.parse::<i32>().ok()?;
+        let host_port = match host_port {
+            Some(host_port) => Some(host_port.parse::<i32>().ok()?),
+            None => None,
+        };
+        Some(PortBinding {
+            container_port,
+            host_port,
+            protocol: String::from(protocol),
+        })
+    }
+}
+
+/// A bind mount or a named volume mounted in the container
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Mount {
+    pub source: String,
+    pub target: String,
+    pub read_only: bool,
+}
+
+impl Mount {
+    /// Builds a mount from its 'source:target[:ro]' representation
+    pub fn parse(mount: &str) -> Option<Self> {
+        let mut parts = mount.split(':');
+        let source = parts.next()?;
+        let target = parts.next()?;
+        let read_only = parts.next().map(|mode| mode == "ro").unwrap_or(false);
+        Some(Mount {
+            source: String::from(source),
+            target: String::from(target),
+            read_only,
+        })
+    }
+}
+
+/// A label attached to the container
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Label {
+    pub key: String,
+    pub value: String,
+}
+
+impl From<&str> for Label {
+    /// Builds a label from its 'key=value' representation
+    fn from(label: &str) -> Self {
+        let mut parts = label.splitn(2, '=');
+        Label {
+            key: String::from(parts.next().unwrap_or_default()),
+            value: String::from(parts.next().unwrap_or_default()),
         }
     }
 }
@@ -0,0 +1,79 @@
+use bollard::network::{CreateNetworkOptions, ListNetworksOptions};
+use slog::{info, warn};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::default::Default;
+
+use crate::api::containers::MANAGED_LABEL;
+use crate::error;
+use crate::state::State;
+
+/// The name of the docker network dedicated to an environment
+pub fn network_name(environment: &str) -> String {
+    format!("{}-network", environment)
+}
+
+/// Create a user-defined bridge network.
+/// Containers attached to it can reach each other by name or alias, but not the containers
+/// attached to other networks.
+pub async fn create_network(name: &str, state: &State) -> Result<(), error::Error> {
+    info!(state.logger, "Creating network {}", name);
+
+    let mut labels = HashMap::new();
+    labels.insert(MANAGED_LABEL, "true");
+
+    let options = CreateNetworkOptions {
+        name,
+        check_duplicate: true,
+        driver: "bridge",
+        labels,
+        ..Default::default()
+    };
+
+    state
+        .docker
+        .create_network(options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not create network",
+        })?;
+
+    Ok(())
+}
+
+/// Remove a network, if it still exists in the docker engine.
+/// The network must no longer have containers attached.
+pub async fn remove_network(name: &str, state: &State) -> Result<(), error::Error> {
+    let mut filters = HashMap::new();
+    filters.insert("name", vec![name]);
+
+    let networks = state
+        .docker
+        .list_networks(Some(ListNetworksOptions { filters }))
+        .await
+        .context(error::BollardError {
+            msg: "Could not list networks",
+        })?;
+
+    // The name filter matches on substrings, so we look for an exact match.
+    if networks
+        .iter()
+        .all(|network| network.name.as_deref() != Some(name))
+    {
+        warn!(
+            state.logger,
+            "Network {} no longer exists in the docker engine", name
+        );
+        return Ok(());
+    }
+
+    info!(state.logger, "Removing network {}", name);
+
+    state
+        .docker
+        .remove_network(name)
+        .await
+        .context(error::BollardError {
+            msg: "Could not remove network",
+        })
+}
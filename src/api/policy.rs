@@ -0,0 +1,238 @@
+use slog::{info, warn, Logger};
+
+use crate::api::reference::Reference;
+use crate::error;
+use crate::settings::Policy;
+
+/// Check an image reference against the image policy.
+/// Returns the reference to pull: digest pinned images are pulled by digest, so that the
+/// image content cannot change under the same tag.
+pub fn check_image(image: &str, policy: &Policy, logger: &Logger) -> Result<String, error::Error> {
+    let reference = Reference::parse(image);
+    let name = reference.name();
+
+    if let Some(pattern) = policy
+        .deny
+        .iter()
+        .find(|pattern| is_denied(pattern, &reference))
+    {
+        return Err(violation(
+            image,
+            format!("matches denied pattern '{}'", pattern),
+            logger,
+        ));
+    }
+
+    if !policy.allow.is_empty()
+        && !policy
+            .allow
+            .iter()
+            .any(|pattern| glob_match(pattern, &name))
+    {
+        return Err(violation(
+            image,
+            String::from("does not match any allowed pattern"),
+            logger,
+        ));
+    }
+
+    let pin = policy.pins.iter().find(|pin| glob_match(&pin.image, &name));
+
+    let resolved = match (pin, &reference.digest) {
+        (None, _) => String::from(image),
+        (Some(pin), Some(digest)) if *digest == pin.digest => String::from(image),
+        (Some(pin), Some(digest)) => {
+            return Err(violation(
+                image,
+                format!(
+                    "digest {} differs from pinned digest {}",
+                    digest, pin.digest
+                ),
+                logger,
+            ));
+        }
+        (Some(pin), None) => format!("{}@{}", image, pin.digest),
+    };
+
+    info!(logger, "Image policy: allowed {} as {}", image, resolved);
+
+    Ok(resolved)
+}
+
+/// Whether a deny pattern matches an image reference.
+/// A pattern without tag denies the whole repository. When the reference carries a digest,
+/// its tag, if any, says nothing about the content pulled, so the reference is denied
+/// whenever its repository is denied, whatever the tag in the pattern.
+fn is_denied(pattern: &str, reference: &Reference) -> bool {
+    let repository = reference.repository_name();
+    if glob_match(pattern, &reference.name()) || glob_match(pattern, &repository) {
+        return true;
+    }
+
+    match &reference.digest {
+        Some(digest) => {
+            glob_match(pattern, &format!("{}@{}", repository, digest))
+                || glob_match(repository_pattern(pattern), &repository)
+        }
+        None => false,
+    }
+}
+
+/// The part of a pattern matching the repository, without tag nor digest.
+fn repository_pattern(pattern: &str) -> &str {
+    let pattern = match pattern.find('@') {
+        Some(idx) => &pattern[..idx],
+        None => pattern,
+    };
+    match pattern.rfind(':') {
+        Some(idx) if !pattern[idx..].contains('/') => &pattern[..idx],
+        _ => pattern,
+    }
+}
+
+fn violation(image: &str, reason: String, logger: &Logger) -> error::Error {
+    warn!(logger, "Image policy: denied {}: {}", image, reason);
+    error::Error::ImagePolicyViolation {
+        image: String::from(image),
+        reason,
+    }
+}
+
+/// Match a name against a glob pattern, where '*' matches any sequence of characters
+/// (including '/'), and '?' any single character.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+
+    // Iterative matching with backtracking on the last '*'.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Pin;
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, slog::o!())
+    }
+
+    fn policy(allow: &[&str], deny: &[&str]) -> Policy {
+        Policy {
+            allow: allow.iter().map(|pattern| String::from(*pattern)).collect(),
+            deny: deny.iter().map(|pattern| String::from(*pattern)).collect(),
+            pins: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn glob_match_literal() {
+        assert!(glob_match(
+            "docker.io/library/postgres:12",
+            "docker.io/library/postgres:12"
+        ));
+        assert!(!glob_match(
+            "docker.io/library/postgres:12",
+            "docker.io/library/postgres:13"
+        ));
+        assert!(!glob_match(
+            "docker.io/library/postgres",
+            "docker.io/library/postgres:12"
+        ));
+    }
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match(
+            "docker.io/library/*",
+            "docker.io/library/postgres:12"
+        ));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*postgres*", "docker.io/library/postgres:12"));
+        assert!(glob_match(
+            "docker.io/*/postgres:1?",
+            "docker.io/library/postgres:12"
+        ));
+        assert!(!glob_match(
+            "docker.io/*/postgres:1?",
+            "docker.io/library/postgres:9"
+        ));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+        assert!(!glob_match("a*b*c", "aXXbYYbd"));
+    }
+
+    #[test]
+    fn deny_takes_precedence() {
+        let policy = policy(&["docker.io/library/*"], &["docker.io/library/ubuntu:*"]);
+        assert!(check_image("postgres:12", &policy, &logger()).is_ok());
+        assert!(check_image("ubuntu:20.04", &policy, &logger()).is_err());
+    }
+
+    #[test]
+    fn deny_digest_references_whatever_the_tag() {
+        let policy = policy(&[], &["docker.io/library/ubuntu:*"]);
+        assert!(check_image("ubuntu@sha256:abcd", &policy, &logger()).is_err());
+        assert!(check_image("debian:10@sha256:abcd", &policy, &logger()).is_ok());
+
+        let policy = policy(&[], &["docker.io/library/ubuntu:14.04"]);
+        assert!(check_image("ubuntu:20.04@sha256:abcd", &policy, &logger()).is_err());
+        assert!(check_image("ubuntu:20.04", &policy, &logger()).is_ok());
+    }
+
+    #[test]
+    fn deny_repository_without_tag() {
+        let policy = policy(&[], &["docker.io/library/ubuntu"]);
+        assert!(check_image("ubuntu", &policy, &logger()).is_err());
+        assert!(check_image("ubuntu:20.04", &policy, &logger()).is_err());
+    }
+
+    #[test]
+    fn deny_docker_hub_aliases() {
+        let policy = policy(&[], &["docker.io/library/ubuntu:*"]);
+        assert!(check_image("index.docker.io/ubuntu:20.04", &policy, &logger()).is_err());
+        assert!(check_image(
+            "registry-1.docker.io/library/ubuntu@sha256:abcd",
+            &policy,
+            &logger()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn allow_list() {
+        let policy = policy(&["registry.acme.com/*"], &[]);
+        assert!(check_image("registry.acme.com/team/app:1.0", &policy, &logger()).is_ok());
+        assert!(check_image("postgres:12", &policy, &logger()).is_err());
+    }
+
+    #[test]
+    fn pins() {
+        let mut policy = policy(&[], &[]);
+        policy.pins.push(Pin {
+            image: String::from("docker.io/library/postgres:12"),
+            digest: String::from("sha256:abcd"),
+        });
+        assert_eq!(
+            check_image("postgres:12", &policy, &logger()).unwrap(),
+            "postgres:12@sha256:abcd"
+        );
+        assert!(check_image("postgres:12@sha256:ef01", &policy, &logger()).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::TryFutureExt;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use slog::{info, warn};
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::api::containers::MANAGED_LABEL;
+use crate::api::model::*;
+use crate::db::model::ProvideData;
+use crate::db::Db;
+use crate::error;
+use crate::settings;
+use crate::state::State;
+
+/// A container carrying this service's label, but which is not recorded in the database.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanContainer {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub state: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// The differences between the database and the docker engine.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftResponseBody {
+    /// Containers recorded in the database, but which no longer exist in the docker engine
+    pub missing: Vec<Container>,
+    /// Containers managed by this service in the docker engine, but not recorded in the database
+    pub orphans: Vec<OrphanContainer>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Compare the containers recorded in the database with the ones in the docker engine,
+/// without changing either.
+pub async fn detect_drift(state: &State) -> Result<DriftResponseBody, error::Error> {
+    let (drift, _) = observe(state).await?;
+    Ok(drift)
+}
+
+/// Compare the containers recorded in the database with the ones in the docker engine.
+/// Returns the drift, and the containers whose state changed, with their new state.
+async fn observe(
+    state: &State,
+) -> Result<(DriftResponseBody, Vec<(String, ContainerState)>), error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entities = tx
+        .get_all_containers()
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get all them containers",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    let options = Some(ListContainersOptions::<String> {
+        all: true,
+        ..Default::default()
+    });
+
+    let summaries = state
+        .docker
+        .list_containers(options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not list containers",
+        })?;
+
+    let known_ids = entities
+        .iter()
+        .map(|entity| entity.id.clone())
+        .collect::<HashSet<_>>();
+
+    let docker_states = summaries
+        .iter()
+        .filter_map(|summary| {
+            summary
+                .id
+                .clone()
+                .map(|id| (id, summary.state.clone().unwrap_or_default()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let orphans = summaries
+        .into_iter()
+        .filter(|summary| {
+            summary
+                .labels
+                .as_ref()
+                .map(|labels| labels.contains_key(MANAGED_LABEL))
+                .unwrap_or(false)
+        })
+        .filter(|summary| {
+            summary
+                .id
+                .as_ref()
+                .map(|id| !known_ids.contains(id))
+                .unwrap_or(false)
+        })
+        .map(|summary| OrphanContainer {
+            id: summary.id.unwrap_or_default(),
+            name: summary
+                .names
+                .and_then(|names| names.into_iter().next())
+                .map(|name| String::from(name.trim_start_matches('/')))
+                .unwrap_or_default(),
+            image: summary.image.unwrap_or_default(),
+            state: summary.state,
+            created_at: summary.created.map(|created| {
+                DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(created, 0), Utc)
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    let mut missing = Vec::new();
+    let mut changes = Vec::new();
+
+    for mut entity in entities {
+        let docker_state = match docker_states.get(&entity.id) {
+            Some(docker_state) => ContainerState::from_str(docker_state).ok(),
+            None => Some(ContainerState::Missing),
+        };
+
+        if let Some(docker_state) = docker_state {
+            if docker_state.as_str() != entity.state {
+                changes.push((entity.name.clone(), docker_state));
+                entity.state = String::from(docker_state.as_str());
+            }
+        }
+
+        if entity.state == ContainerState::Missing.as_str() {
+            missing.push(Container::from(entity));
+        }
+    }
+
+    let drift = DriftResponseBody {
+        missing,
+        orphans,
+        checked_at: Utc::now(),
+    };
+
+    Ok((drift, changes))
+}
+
+/// Whether an orphan container is old enough to be garbage collected.
+/// A container is created in the docker engine before it is recorded in the database,
+/// so a recent orphan may just be in the making. Without creation time, it is not
+/// removed.
+fn is_collectable(orphan: &OrphanContainer, now: DateTime<Utc>, grace_period: u64) -> bool {
+    orphan.created_at.map_or(false, |created_at| {
+        now - created_at > chrono::Duration::seconds(grace_period as i64)
+    })
+}
+
+/// Detect the drift between the database and the docker engine, record the state of the
+/// containers in the database, and optionally garbage collect: orphan containers older
+/// than the grace period are removed from the docker engine, and the records of missing
+/// containers are deleted from the database.
+pub async fn reconcile(
+    state: &State,
+    settings: &settings::Reconciler,
+) -> Result<DriftResponseBody, error::Error> {
+    let (drift, changes) = observe(state).await?;
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    for (name, container_state) in changes.iter() {
+        info!(
+            state.logger,
+            "Container {} is now {}",
+            name,
+            container_state.as_str()
+        );
+
+        ProvideData::update_container_state(
+            &mut tx as &mut sqlx::PgConnection,
+            name,
+            container_state.as_str(),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not record container state",
+        })?;
+    }
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit reconciliation transaction",
+    })?;
+
+    if !settings.garbage_collect {
+        return Ok(drift);
+    }
+
+    for orphan in drift
+        .orphans
+        .iter()
+        .filter(|orphan| is_collectable(orphan, drift.checked_at, settings.grace_period))
+    {
+        warn!(state.logger, "Removing orphan container {}", orphan.name);
+        let options = Some(RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        });
+        state
+            .docker
+            .remove_container(&orphan.id, options)
+            .await
+            .context(error::BollardError {
+                msg: "Could not remove orphan container",
+            })?;
+    }
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    for container in drift.missing.iter() {
+        warn!(
+            state.logger,
+            "Deleting record of missing container {}", container.name
+        );
+        ProvideData::delete_container_by_name(&mut tx as &mut sqlx::PgConnection, &container.name)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not delete missing container",
+            })?;
+    }
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit garbage collection transaction",
+    })?;
+
+    Ok(drift)
+}
+
+/// Periodically reconcile the database with the docker engine.
+/// This is meant to be spawned in the background when the server starts.
+pub async fn run_reconciler(state: State, settings: settings::Reconciler) {
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.interval));
+    loop {
+        interval.tick().await;
+        match reconcile(&state, &settings).await {
+            Ok(drift) => {
+                if !drift.missing.is_empty() || !drift.orphans.is_empty() {
+                    warn!(
+                        state.logger,
+                        "Drift detected: {} missing, {} orphans",
+                        drift.missing.len(),
+                        drift.orphans.len()
+                    );
+                }
+            }
+            Err(err) => {
+                warn!(state.logger, "Reconciliation failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn orphan(created_at: Option<DateTime<Utc>>) -> OrphanContainer {
+        OrphanContainer {
+            id: String::from("abcd"),
+            name: String::from("orphan"),
+            image: String::from("postgres"),
+            state: Some(String::from("running")),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn recent_orphans_are_not_collectable() {
+        let now = Utc::now();
+        let orphan = orphan(Some(now - chrono::Duration::seconds(30)));
+        assert!(!is_collectable(&orphan, now, 300));
+    }
+
+    #[test]
+    fn old_orphans_are_collectable() {
+        let now = Utc::now();
+        let orphan = orphan(Some(now - chrono::Duration::seconds(600)));
+        assert!(is_collectable(&orphan, now, 300));
+    }
+
+    #[test]
+    fn orphans_without_creation_time_are_not_collectable() {
+        assert!(!is_collectable(&orphan(None), Utc::now(), 0));
+    }
+}
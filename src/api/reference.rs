@@ -0,0 +1,152 @@
+/// The registry used for images without registry host, eg 'postgres:12'
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Other names of the docker hub, which are normalized to the default registry.
+const DEFAULT_REGISTRY_ALIASES: [&str; 3] = [
+    "index.docker.io",
+    "registry-1.docker.io",
+    "registry.hub.docker.com",
+];
+
+/// An image reference, broken down in its components.
+/// 'postgres' is short for 'docker.io/library/postgres:latest'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl Reference {
+    pub fn parse(image: &str) -> Self {
+        let (name, digest) = match image.find('@') {
+            Some(idx) => (&image[..idx], Some(String::from(&image[idx + 1..]))),
+            None => (image, None),
+        };
+
+        // The tag follows the last ':', unless it is part of the registry host (port).
+        let (name, tag) = match name.rfind(':') {
+            Some(idx) if !name[idx..].contains('/') => {
+                (&name[..idx], Some(String::from(&name[idx + 1..])))
+            }
+            _ => (name, None),
+        };
+        let tag = tag.or_else(|| {
+            if digest.is_none() {
+                Some(String::from("latest"))
+            } else {
+                None
+            }
+        });
+
+        let (registry, repository) = match name.find('/') {
+            Some(idx) if is_registry_host(&name[..idx]) => {
+                (name[..idx].to_lowercase(), String::from(&name[idx + 1..]))
+            }
+            _ => (String::from(DEFAULT_REGISTRY), String::from(name)),
+        };
+
+        let registry = if DEFAULT_REGISTRY_ALIASES.contains(&registry.as_str()) {
+            String::from(DEFAULT_REGISTRY)
+        } else {
+            registry
+        };
+
+        // Official images of the docker hub live in the 'library' namespace.
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+
+        Reference {
+            registry,
+            repository,
+            tag,
+            digest,
+        }
+    }
+
+    /// The fully qualified repository, eg 'docker.io/library/postgres', without tag nor
+    /// digest.
+    pub fn repository_name(&self) -> String {
+        format!("{}/{}", self.registry, self.repository)
+    }
+
+    /// The fully qualified name and tag, eg 'docker.io/library/postgres:12'.
+    pub fn name(&self) -> String {
+        match &self.tag {
+            Some(tag) => format!("{}:{}", self.repository_name(), tag),
+            None => self.repository_name(),
+        }
+    }
+}
+
+/// Following the docker conventions, the first component of a reference is a registry
+/// host only if it contains a '.' or a ':', or is 'localhost'.
+fn is_registry_host(host: &str) -> bool {
+    host.contains('.') || host.contains(':') || host == "localhost"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_short_name() {
+        let reference = Reference::parse("postgres");
+        assert_eq!(reference.registry, "docker.io");
+        assert_eq!(reference.repository, "library/postgres");
+        assert_eq!(reference.tag.as_deref(), Some("latest"));
+        assert_eq!(reference.digest, None);
+        assert_eq!(reference.name(), "docker.io/library/postgres:latest");
+    }
+
+    #[test]
+    fn parse_namespaced_name_with_tag() {
+        let reference = Reference::parse("bitnami/redis:6.0");
+        assert_eq!(reference.registry, "docker.io");
+        assert_eq!(reference.repository, "bitnami/redis");
+        assert_eq!(reference.tag.as_deref(), Some("6.0"));
+    }
+
+    #[test]
+    fn parse_registry_with_port() {
+        let reference = Reference::parse("registry.acme.com:5000/team/app");
+        assert_eq!(reference.registry, "registry.acme.com:5000");
+        assert_eq!(reference.repository, "team/app");
+        assert_eq!(reference.tag.as_deref(), Some("latest"));
+
+        let reference = Reference::parse("localhost:5000/app:1.2");
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.repository, "app");
+        assert_eq!(reference.tag.as_deref(), Some("1.2"));
+    }
+
+    #[test]
+    fn parse_digest() {
+        let reference = Reference::parse("ubuntu@sha256:abcd");
+        assert_eq!(reference.repository_name(), "docker.io/library/ubuntu");
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.digest.as_deref(), Some("sha256:abcd"));
+        assert_eq!(reference.name(), "docker.io/library/ubuntu");
+
+        let reference = Reference::parse("ubuntu:20.04@sha256:abcd");
+        assert_eq!(reference.tag.as_deref(), Some("20.04"));
+        assert_eq!(reference.digest.as_deref(), Some("sha256:abcd"));
+    }
+
+    #[test]
+    fn parse_normalizes_docker_hub_aliases() {
+        for image in &[
+            "index.docker.io/ubuntu",
+            "registry-1.docker.io/library/ubuntu",
+            "Registry.Hub.Docker.com/ubuntu",
+            "docker.io/ubuntu",
+        ] {
+            let reference = Reference::parse(image);
+            assert_eq!(reference.name(), "docker.io/library/ubuntu:latest");
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use bollard::auth::DockerCredentials;
+use chrono::{DateTime, Utc};
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use slog::info;
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::convert::TryFrom;
+use std::default::Default;
+
+use crate::api::gql::Context;
+use crate::api::reference::Reference;
+use crate::db::model::{ProvideData, RegistryEntity};
+use crate::db::Db;
+use crate::error;
+use crate::state::State;
+
+/// A private registry for which we hold credentials
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct Registry {
+    pub registry: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<RegistryEntity> for Registry {
+    fn from(entity: RegistryEntity) -> Self {
+        let RegistryEntity {
+            registry,
+            username,
+            created_at,
+            updated_at,
+        } = entity;
+
+        Registry {
+            registry,
+            username,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// The response body for single registry
+/// It is optional, since we may be looking for a registry which
+/// does not match the query criteria.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleRegistryResponseBody {
+    pub registry: Option<Registry>,
+}
+
+/// The response body for multiple registries
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiRegistriesResponseBody {
+    pub registries: Vec<Registry>,
+    pub registries_count: i32,
+}
+
+impl From<Vec<Registry>> for MultiRegistriesResponseBody {
+    fn from(registries: Vec<Registry>) -> Self {
+        let registries_count = i32::try_from(registries.len()).unwrap();
+        Self {
+            registries,
+            registries_count,
+        }
+    }
+}
+
+/// The query body for registering or rotating the credentials of a registry,
+/// given by its host, eg 'registry.acme.com:5000'
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryCredentialsRequestBody {
+    pub registry: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// Retrieve all the registries we hold credentials for.
+pub async fn list_registries(
+    context: &Context,
+) -> Result<MultiRegistriesResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entities = tx
+            .get_all_registries()
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get all them registries",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let registries = entities.into_iter().map(Registry::from).collect::<Vec<_>>();
+
+        Ok(MultiRegistriesResponseBody::from(registries))
+    }
+    .await
+}
+
+/// Store the credentials of a new registry.
+pub async fn register_registry_credentials(
+    credentials: RegistryCredentialsRequestBody,
+    context: &Context,
+) -> Result<SingleRegistryResponseBody, error::Error> {
+    async move {
+        info!(
+            context.state.logger,
+            "Registering credentials for registry {}", credentials.registry
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::create_registry_credentials(
+            &mut tx as &mut sqlx::PgConnection,
+            &credentials.registry,
+            &credentials.username,
+            &credentials.password,
+            &context.state.registry.secret,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create registry credentials",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit registry credentials transaction",
+        })?;
+
+        Ok(SingleRegistryResponseBody {
+            registry: Some(Registry::from(entity)),
+        })
+    }
+    .await
+}
+
+/// Replace the credentials of a registry.
+pub async fn rotate_registry_credentials(
+    credentials: RegistryCredentialsRequestBody,
+    context: &Context,
+) -> Result<SingleRegistryResponseBody, error::Error> {
+    async move {
+        info!(
+            context.state.logger,
+            "Rotating credentials for registry {}", credentials.registry
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::update_registry_credentials(
+            &mut tx as &mut sqlx::PgConnection,
+            &credentials.registry,
+            &credentials.username,
+            &credentials.password,
+            &context.state.registry.secret,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not update registry credentials",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit registry credentials transaction",
+        })?;
+
+        Ok(SingleRegistryResponseBody {
+            registry: entity.map(Registry::from),
+        })
+    }
+    .await
+}
+
+/// Forget the credentials of a registry.
+pub async fn delete_registry_credentials(
+    registry: &str,
+    context: &Context,
+) -> Result<SingleRegistryResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity =
+            ProvideData::delete_registry_credentials(&mut tx as &mut sqlx::PgConnection, registry)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not delete registry credentials",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit registry credentials transaction",
+        })?;
+
+        Ok(SingleRegistryResponseBody {
+            registry: entity.map(Registry::from),
+        })
+    }
+    .await
+}
+
+/// The credentials to use for pulling an image, if we hold some for its registry.
+pub async fn credentials_for_image(
+    image: &str,
+    state: &State,
+) -> Result<Option<DockerCredentials>, error::Error> {
+    let registry = Reference::parse(image).registry;
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let credentials = ProvideData::get_registry_credentials(
+        &mut tx as &mut sqlx::PgConnection,
+        &registry,
+        &state.registry.secret,
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not get registry credentials",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(credentials.map(|credentials| DockerCredentials {
+        username: Some(credentials.username),
+        password: Some(credentials.password),
+        serveraddress: Some(registry),
+        ..Default::default()
+    }))
+}
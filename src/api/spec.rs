@@ -0,0 +1,728 @@
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::api::containers::{
+    ContainerRequestBody, EnvVarRequestBody, HealthcheckRequestBody, MountRequestBody,
+    PortBindingRequestBody,
+};
+use crate::api::environments::{
+    provision_environment, EnvironmentRequestBody, SingleEnvironmentResponseBody,
+};
+use crate::api::gql::Context;
+use crate::error;
+
+/// The subset of the docker-compose file format we understand.
+/// Unknown keys are rejected, rather than silently ignored.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComposeFile {
+    version: Option<String>,
+    name: Option<String>,
+    services: BTreeMap<String, ComposeService>,
+    /// The named volumes used by the services. Their configuration is ignored.
+    #[serde(default)]
+    volumes: BTreeMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComposeService {
+    image: Option<String>,
+    #[serde(default)]
+    environment: Option<KeyValues>,
+    #[serde(default)]
+    ports: Vec<serde_yaml::Value>,
+    #[serde(default)]
+    volumes: Vec<String>,
+    #[serde(default)]
+    depends_on: Option<DependsOn>,
+    healthcheck: Option<ComposeHealthcheck>,
+}
+
+/// Compose accepts both ['KEY=value'] and { KEY: value }
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum KeyValues {
+    List(Vec<String>),
+    Map(BTreeMap<String, serde_yaml::Value>),
+}
+
+/// Compose accepts both [service] and { service: { condition: ... } }
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DependsOn {
+    List(Vec<String>),
+    Map(BTreeMap<String, Dependency>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Dependency {
+    condition: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComposeHealthcheck {
+    test: Option<StringOrList>,
+    interval: Option<String>,
+    timeout: Option<String>,
+    retries: Option<i32>,
+    start_period: Option<String>,
+    #[serde(default)]
+    disable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StringOrList {
+    String(String),
+    List(Vec<String>),
+}
+
+/// A validated environment spec.
+/// The containers of the environment are listed in dependency order, so that a container
+/// is always created after the containers it depends on.
+#[derive(Debug)]
+pub struct EnvironmentSpec {
+    pub environment: EnvironmentRequestBody,
+    /// The names of the containers which must be healthy before their dependents are created.
+    pub await_healthy: HashSet<String>,
+}
+
+impl EnvironmentSpec {
+    /// Parse and validate a compose-like YAML document.
+    /// Containers are named after the environment and the service, eg 'myenv-db', and can be
+    /// reached by the service name from the other containers of the environment.
+    pub fn parse(yaml: &str, owner: Option<String>) -> Result<Self, error::Error> {
+        let compose: ComposeFile = serde_yaml::from_str(yaml).context(error::YAMLError {
+            msg: "Could not parse environment spec",
+        })?;
+
+        if let Some(version) = compose.version.as_deref() {
+            if !version.starts_with('3') {
+                return Err(spec_error(format!(
+                    "Unsupported compose version '{}'",
+                    version
+                )));
+            }
+        }
+
+        let name = compose
+            .name
+            .ok_or_else(|| spec_error(String::from("The spec must have a name")))?;
+        validate_name("environment", &name)?;
+
+        if compose.services.is_empty() {
+            return Err(spec_error(String::from(
+                "The spec must have at least one service",
+            )));
+        }
+
+        let mut dependencies = HashMap::new();
+        let mut await_healthy = HashSet::new();
+        for (service_name, service) in compose.services.iter() {
+            validate_name("service", service_name)?;
+            let depends_on = service_dependencies(service_name, service)?;
+            for (dependency, healthy) in depends_on.iter() {
+                let target = compose.services.get(dependency).ok_or_else(|| {
+                    spec_error(format!(
+                        "Service '{}' depends on unknown service '{}'",
+                        service_name, dependency
+                    ))
+                })?;
+                if *healthy {
+                    if target.healthcheck.as_ref().map_or(true, |h| h.disable) {
+                        return Err(spec_error(format!(
+                            "Service '{}' waits for service '{}' to be healthy, but it has no healthcheck",
+                            service_name, dependency
+                        )));
+                    }
+                    await_healthy.insert(container_name(&name, dependency));
+                }
+            }
+            dependencies.insert(
+                service_name.clone(),
+                depends_on
+                    .into_iter()
+                    .map(|(d, _)| d)
+                    .collect::<BTreeSet<_>>(),
+            );
+        }
+
+        let order = dependency_order(&dependencies)?;
+
+        let volumes = compose.volumes.keys().cloned().collect::<HashSet<_>>();
+        let mut services = compose.services;
+        let containers = order
+            .into_iter()
+            .map(|service_name| {
+                let service = services.remove(&service_name).unwrap();
+                container_request(&name, &service_name, service, &volumes)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(EnvironmentSpec {
+            environment: EnvironmentRequestBody {
+                name,
+                description: None,
+                owner,
+                containers,
+                expires_at: None,
+                ttl: None,
+            },
+            await_healthy,
+        })
+    }
+}
+
+/// Create an environment from a compose-like YAML spec.
+/// The containers are created in dependency order.
+/// The environment expires after the time to live (in seconds), if one is given.
+pub async fn create_environment_from_spec(
+    yaml: &str,
+    owner: Option<String>,
+    ttl: Option<i32>,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    let mut spec = EnvironmentSpec::parse(yaml, owner)?;
+    spec.environment.ttl = ttl;
+
+    provision_environment(spec.environment, &spec.await_healthy, context).await
+}
+
+fn spec_error(msg: String) -> error::Error {
+    error::Error::SpecError { msg }
+}
+
+/// Names end up in docker container names, so we restrict them to what docker accepts.
+fn validate_name(kind: &str, name: &str) -> Result<(), error::Error> {
+    let valid = name
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(spec_error(format!("Invalid {} name '{}'", kind, name)))
+    }
+}
+
+fn container_name(environment: &str, service: &str) -> String {
+    format!("{}-{}", environment, service)
+}
+
+/// The services a service depends on, and whether it waits for them to be healthy.
+fn service_dependencies(
+    service_name: &str,
+    service: &ComposeService,
+) -> Result<Vec<(String, bool)>, error::Error> {
+    match &service.depends_on {
+        None => Ok(vec![]),
+        Some(DependsOn::List(list)) => Ok(list.iter().map(|d| (d.clone(), false)).collect()),
+        Some(DependsOn::Map(map)) => map
+            .iter()
+            .map(|(dependency, spec)| match spec.condition.as_deref() {
+                None | Some("service_started") => Ok((dependency.clone(), false)),
+                Some("service_healthy") => Ok((dependency.clone(), true)),
+                Some(condition) => Err(spec_error(format!(
+                    "Service '{}' has an unsupported condition '{}' on '{}'",
+                    service_name, condition, dependency
+                ))),
+            })
+            .collect(),
+    }
+}
+
+/// Sort the services so that each one comes after its dependencies (Kahn's algorithm).
+/// Services without dependencies between them keep their alphabetical order.
+fn dependency_order(
+    dependencies: &HashMap<String, BTreeSet<String>>,
+) -> Result<Vec<String>, error::Error> {
+    let mut remaining: BTreeMap<&str, usize> = dependencies
+        .iter()
+        .map(|(service, depends_on)| (service.as_str(), depends_on.len()))
+        .collect();
+
+    let mut ready: VecDeque<&str> = remaining
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(service, _)| *service)
+        .collect();
+
+    let mut order = Vec::new();
+    while let Some(service) = ready.pop_front() {
+        remaining.remove(service);
+        order.push(String::from(service));
+        let mut unblocked = Vec::new();
+        for (dependent, count) in remaining.iter_mut() {
+            if dependencies[*dependent].contains(service) {
+                *count -= 1;
+                if *count == 0 {
+                    unblocked.push(*dependent);
+                }
+            }
+        }
+        ready.extend(unblocked);
+    }
+
+    if remaining.is_empty() {
+        Ok(order)
+    } else {
+        let cycle = remaining.keys().cloned().collect::<Vec<_>>().join(", ");
+        Err(spec_error(format!(
+            "Circular dependency between services {}",
+            cycle
+        )))
+    }
+}
+
+fn container_request(
+    environment: &str,
+    service_name: &str,
+    service: ComposeService,
+    volumes: &HashSet<String>,
+) -> Result<ContainerRequestBody, error::Error> {
+    let image = service
+        .image
+        .ok_or_else(|| spec_error(format!("Service '{}' has no image", service_name)))?;
+
+    let env = match service.environment {
+        None => None,
+        Some(environment) => Some(env_vars(service_name, environment)?),
+    };
+
+    let ports = service
+        .ports
+        .iter()
+        .map(|port| port_binding(service_name, port))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mounts = service
+        .volumes
+        .iter()
+        .map(|volume| mount(environment, service_name, volume, volumes))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let healthcheck = match service.healthcheck {
+        None => None,
+        Some(healthcheck) => Some(healthcheck_request(service_name, healthcheck)?),
+    };
+
+    Ok(ContainerRequestBody {
+        name: container_name(environment, service_name),
+        image,
+        env,
+        cmd: None,
+        entrypoint: None,
+        ports: Some(ports),
+        mounts: Some(mounts),
+        working_dir: None,
+        labels: None,
+        resources: None,
+        restart_policy: None,
+        healthcheck,
+        aliases: Some(vec![String::from(service_name)]),
+    })
+}
+
+fn env_vars(service_name: &str, env: KeyValues) -> Result<Vec<EnvVarRequestBody>, error::Error> {
+    match env {
+        KeyValues::List(list) => Ok(list
+            .into_iter()
+            .map(|env| {
+                let mut parts = env.splitn(2, '=');
+                EnvVarRequestBody {
+                    name: String::from(parts.next().unwrap_or_default()),
+                    value: String::from(parts.next().unwrap_or_default()),
+                }
+            })
+            .collect()),
+        KeyValues::Map(map) => map
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_yaml::Value::Null => String::new(),
+                    serde_yaml::Value::Bool(b) => b.to_string(),
+                    serde_yaml::Value::Number(n) => n.to_string(),
+                    serde_yaml::Value::String(s) => s,
+                    _ => {
+                        return Err(spec_error(format!(
+                            "Service '{}' has an invalid value for environment variable '{}'",
+                            service_name, name
+                        )))
+                    }
+                };
+                Ok(EnvVarRequestBody { name, value })
+            })
+            .collect(),
+    }
+}
+
+/// Parse a port in the short compose syntax: '80', '8080:80' or '8080:80/udp'.
+/// Host IPs and port ranges are not supported.
+fn port_binding(
+    service_name: &str,
+    port: &serde_yaml::Value,
+) -> Result<PortBindingRequestBody, error::Error> {
+    let invalid = |port: &str| {
+        spec_error(format!(
+            "Service '{}' has an invalid port '{}'",
+            service_name, port
+        ))
+    };
+
+    let port = match port {
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::String(s) => s.clone(),
+        _ => return Err(invalid(&format!("{:?}", port))),
+    };
+
+    let (ports, protocol) = match port.find('/') {
+        Some(idx) => (&port[..idx], Some(&port[idx + 1..])),
+        None => (port.as_str(), None),
+    };
+    if let Some(protocol) = protocol {
+        if protocol != "tcp" && protocol != "udp" {
+            return Err(invalid(&port));
+        }
+    }
+
+    let parts = ports.split(':').collect::<Vec<_>>();
+    let parse = |p: &str| p.parse::<u16>().map(i32::from).map_err(|_| invalid(&port));
+    let (host_port, container_port) = match parts.as_slice() {
+        [container_port] => (None, parse(container_port)?),
+        [host_port, container_port] => (Some(parse(host_port)?), parse(container_port)?),
+        _ => return Err(invalid(&port)),
+    };
+
+    Ok(PortBindingRequestBody {
+        container_port,
+        host_port,
+        protocol: protocol.map(String::from),
+    })
+}
+
+/// Parse a volume in the short compose syntax: 'volume:/target[:ro]' or '/source:/target[:ro]'.
+/// Relative paths and anonymous volumes are not supported.
+/// Named volumes must be declared, and are prefixed with the environment name, eg 'myenv-data',
+/// so that an environment recreated with the same name finds its data again.
+fn mount(
+    environment: &str,
+    service_name: &str,
+    volume: &str,
+    volumes: &HashSet<String>,
+) -> Result<MountRequestBody, error::Error> {
+    let invalid = || {
+        spec_error(format!(
+            "Service '{}' has an invalid volume '{}'",
+            service_name, volume
+        ))
+    };
+
+    let parts = volume.split(':').collect::<Vec<_>>();
+    let (source, target, read_only) = match parts.as_slice() {
+        [source, target] => (*source, *target, false),
+        [source, target, "ro"] => (*source, *target, true),
+        [source, target, "rw"] => (*source, *target, false),
+        _ => return Err(invalid()),
+    };
+
+    if source.is_empty() || source.starts_with('.') || source.starts_with('~') {
+        return Err(invalid());
+    }
+    if !target.starts_with('/') {
+        return Err(invalid());
+    }
+
+    let source = if source.starts_with('/') {
+        String::from(source)
+    } else if volumes.contains(source) {
+        format!("{}-{}", environment, source)
+    } else {
+        return Err(spec_error(format!(
+            "Service '{}' uses undeclared volume '{}'",
+            service_name, source
+        )));
+    };
+
+    Ok(MountRequestBody {
+        source,
+        target: String::from(target),
+        read_only: Some(read_only),
+    })
+}
+
+fn healthcheck_request(
+    service_name: &str,
+    healthcheck: ComposeHealthcheck,
+) -> Result<HealthcheckRequestBody, error::Error> {
+    let test = if healthcheck.disable {
+        vec![String::from("NONE")]
+    } else {
+        match healthcheck.test {
+            None => {
+                return Err(spec_error(format!(
+                    "Service '{}' has a healthcheck without test",
+                    service_name
+                )))
+            }
+            // A plain string is run by the container's shell.
+            Some(StringOrList::String(test)) => vec![String::from("CMD-SHELL"), test],
+            Some(StringOrList::List(test)) => {
+                match test.first().map(String::as_str) {
+                    Some("CMD") | Some("CMD-SHELL") | Some("NONE") => test,
+                    _ => {
+                        return Err(spec_error(format!(
+                            "Service '{}' has a healthcheck test which does not start with CMD, CMD-SHELL or NONE",
+                            service_name
+                        )))
+                    }
+                }
+            }
+        }
+    };
+
+    let seconds = |duration: Option<String>| -> Result<Option<i32>, error::Error> {
+        duration
+            .map(|duration| {
+                parse_duration(&duration).ok_or_else(|| {
+                    spec_error(format!(
+                        "Service '{}' has an invalid healthcheck duration '{}'",
+                        service_name, duration
+                    ))
+                })
+            })
+            .transpose()
+    };
+
+    Ok(HealthcheckRequestBody {
+        test,
+        interval_seconds: seconds(healthcheck.interval)?,
+        timeout_seconds: seconds(healthcheck.timeout)?,
+        retries: healthcheck.retries,
+        start_period_seconds: seconds(healthcheck.start_period)?,
+    })
+}
+
+/// Parse a compose duration, eg '30s', '1m30s' or '1h', into seconds.
+fn parse_duration(duration: &str) -> Option<i32> {
+    let mut total = 0i32;
+    let mut digits = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            let value = digits.parse::<i32>().ok()?;
+            digits.clear();
+            let unit = match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return None,
+            };
+            total = total.checked_add(value.checked_mul(unit)?)?;
+        }
+    }
+    // A trailing number without unit is invalid.
+    if digits.is_empty() && !duration.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<EnvironmentSpec, error::Error> {
+        EnvironmentSpec::parse(yaml, None)
+    }
+
+    fn spec_error_message(res: Result<EnvironmentSpec, error::Error>) -> String {
+        match res {
+            Err(error::Error::SpecError { msg }) => msg,
+            res => panic!("unexpected {:?}", res.map(|spec| spec.environment.name)),
+        }
+    }
+
+    #[test]
+    fn parse_orders_containers_by_dependency() {
+        let spec = parse(
+            r#"
+name: shop
+services:
+  web:
+    image: nginx
+    depends_on: [api]
+  api:
+    image: node
+    depends_on:
+      db:
+        condition: service_healthy
+  db:
+    image: postgres
+    healthcheck:
+      test: pg_isready
+      interval: 10s
+"#,
+        )
+        .unwrap();
+        let names = spec
+            .environment
+            .containers
+            .iter()
+            .map(|container| container.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["shop-db", "shop-api", "shop-web"]);
+        assert!(spec.await_healthy.contains("shop-db"));
+        assert_eq!(spec.await_healthy.len(), 1);
+    }
+
+    #[test]
+    fn parse_rejects_cycles() {
+        let msg = spec_error_message(parse(
+            r#"
+name: shop
+services:
+  api:
+    image: node
+    depends_on: [db]
+  db:
+    image: postgres
+    depends_on: [api]
+  web:
+    image: nginx
+"#,
+        ));
+        assert_eq!(msg, "Circular dependency between services api, db");
+    }
+
+    #[test]
+    fn parse_rejects_self_dependency() {
+        let msg = spec_error_message(parse(
+            r#"
+name: shop
+services:
+  api:
+    image: node
+    depends_on: [api]
+"#,
+        ));
+        assert!(msg.starts_with("Circular dependency"));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_dependencies() {
+        let msg = spec_error_message(parse(
+            r#"
+name: shop
+services:
+  api:
+    image: node
+    depends_on: [cache]
+"#,
+        ));
+        assert_eq!(msg, "Service 'api' depends on unknown service 'cache'");
+    }
+
+    #[test]
+    fn parse_rejects_healthy_dependency_without_healthcheck() {
+        let msg = spec_error_message(parse(
+            r#"
+name: shop
+services:
+  api:
+    image: node
+    depends_on:
+      db:
+        condition: service_healthy
+  db:
+    image: postgres
+"#,
+        ));
+        assert!(msg.contains("has no healthcheck"));
+    }
+
+    #[test]
+    fn port_binding_accepts_short_syntax() {
+        let port = port_binding("web", &serde_yaml::Value::from(80)).unwrap();
+        assert_eq!(port.container_port, 80);
+        assert_eq!(port.host_port, None);
+
+        let port = port_binding("web", &serde_yaml::Value::from("8080:80/udp")).unwrap();
+        assert_eq!(port.container_port, 80);
+        assert_eq!(port.host_port, Some(8080));
+        assert_eq!(port.protocol.as_deref(), Some("udp"));
+    }
+
+    #[test]
+    fn port_binding_rejects_malformed_ports() {
+        for port in &[
+            "http",
+            "80/sctp",
+            "127.0.0.1:8080:80",
+            "8000-8010:80",
+            "70000",
+            "8080:",
+            "",
+        ] {
+            assert!(
+                port_binding("web", &serde_yaml::Value::from(*port)).is_err(),
+                "{}",
+                port
+            );
+        }
+        assert!(port_binding("web", &serde_yaml::Value::from(-80)).is_err());
+        assert!(port_binding("web", &serde_yaml::Value::Null).is_err());
+    }
+
+    #[test]
+    fn parse_duration_accepts_compose_durations() {
+        assert_eq!(parse_duration("30s"), Some(30));
+        assert_eq!(parse_duration("1m30s"), Some(90));
+        assert_eq!(parse_duration("1h"), Some(3600));
+        assert_eq!(parse_duration("0s"), Some(0));
+    }
+
+    #[test]
+    fn parse_duration_rejects_malformed_durations() {
+        for duration in &["", "30", "1m30", "s", "1.5s", "10ms", "-5s", "99999999h"] {
+            assert_eq!(parse_duration(duration), None, "{}", duration);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_healthcheck_durations() {
+        let msg = spec_error_message(parse(
+            r#"
+name: shop
+services:
+  db:
+    image: postgres
+    healthcheck:
+      test: pg_isready
+      interval: 10 seconds
+"#,
+        ));
+        assert!(msg.contains("invalid healthcheck duration"));
+    }
+
+    #[test]
+    fn mount_prefixes_named_volumes() {
+        let volumes = vec![String::from("data")]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let mount = mount("shop", "db", "data:/var/lib/postgresql:ro", &volumes).unwrap();
+        assert_eq!(mount.source, "shop-data");
+        assert_eq!(mount.read_only, Some(true));
+        assert!(mount("shop", "db", "cache:/cache", &volumes).is_err());
+        assert!(mount("shop", "db", "./data:/data", &volumes).is_err());
+        assert!(mount("shop", "db", "data:data", &volumes).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use bollard::container::{Stats, StatsOptions};
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt};
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+
+use crate::api::containers::find_managed_container;
+use crate::api::gql::Context;
+use crate::error;
+use crate::state::State;
+
+/// The resource usage of a container.
+/// Sizes are in bytes, and network and block IO are cumulated since the container started.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerStats {
+    pub name: String,
+    pub cpu_percent: f64,
+    pub memory_usage: f64,
+    pub memory_limit: f64,
+    pub memory_percent: f64,
+    pub network_rx: f64,
+    pub network_tx: f64,
+    pub block_read: f64,
+    pub block_write: f64,
+    pub read_at: DateTime<Utc>,
+}
+
+impl From<Stats> for ContainerStats {
+    /// Computes the statistics the same way the docker CLI does.
+    fn from(stats: Stats) -> Self {
+        let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
+            - stats.precpu_stats.cpu_usage.total_usage as f64;
+        let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as f64
+            - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
+        let online_cpus = stats
+            .cpu_stats
+            .online_cpus
+            .or_else(|| {
+                stats
+                    .cpu_stats
+                    .cpu_usage
+                    .percpu_usage
+                    .as_ref()
+                    .map(|percpu| percpu.len() as u64)
+            })
+            .unwrap_or(1) as f64;
+        let cpu_percent = if cpu_delta > 0.0 && system_delta > 0.0 {
+            cpu_delta / system_delta * online_cpus * 100.0
+        } else {
+            0.0
+        };
+
+        let memory_usage = stats.memory_stats.usage.unwrap_or(0) as f64;
+        let memory_limit = stats.memory_stats.limit.unwrap_or(0) as f64;
+        let memory_percent = if memory_limit > 0.0 {
+            memory_usage / memory_limit * 100.0
+        } else {
+            0.0
+        };
+
+        let (network_rx, network_tx) = stats
+            .networks
+            .iter()
+            .flat_map(|networks| networks.values())
+            .fold((0.0, 0.0), |(rx, tx), network| {
+                (rx + network.rx_bytes as f64, tx + network.tx_bytes as f64)
+            });
+
+        let (block_read, block_write) = stats
+            .blkio_stats
+            .io_service_bytes_recursive
+            .iter()
+            .flatten()
+            .fold((0.0, 0.0), |(read, write), entry| {
+                if entry.op.eq_ignore_ascii_case("read") {
+                    (read + entry.value as f64, write)
+                } else if entry.op.eq_ignore_ascii_case("write") {
+                    (read, write + entry.value as f64)
+                } else {
+                    (read, write)
+                }
+            });
+
+        let read_at = DateTime::parse_from_rfc3339(&stats.read)
+            .map(|read_at| read_at.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now());
+
+        ContainerStats {
+            name: String::from(stats.name.trim_start_matches('/')),
+            cpu_percent,
+            memory_usage,
+            memory_limit,
+            memory_percent,
+            network_rx,
+            network_tx,
+            block_read,
+            block_write,
+            read_at,
+        }
+    }
+}
+
+/// Retrieve the current resource usage of a managed container
+pub async fn container_stats(
+    name: &str,
+    context: &Context,
+) -> Result<ContainerStats, error::Error> {
+    async move {
+        find_managed_container(name, context).await?;
+
+        read_stats(name, &context.state).await
+    }
+    .await
+}
+
+/// Read the current resource usage of a container from the docker engine.
+pub async fn read_stats(name: &str, state: &State) -> Result<ContainerStats, error::Error> {
+    let options = Some(StatsOptions { stream: false });
+
+    let stats = state
+        .docker
+        .stats(name, options)
+        .try_next()
+        .await
+        .context(error::BollardError {
+            msg: "Could not get container stats",
+        })?
+        .ok_or_else(|| error::Error::MiscError {
+            msg: format!("No stats reported for container {}", name),
+        })?;
+
+    Ok(ContainerStats::from(stats))
+}
+
+/// Follow the resource usage of a managed container.
+/// The docker engine reports new statistics every second.
+pub async fn follow_container_stats(
+    name: &str,
+    context: &Context,
+) -> Result<impl Stream<Item = Result<ContainerStats, error::Error>> + Send + 'static, error::Error>
+{
+    find_managed_container(name, context).await?;
+
+    let options = Some(StatsOptions { stream: true });
+
+    Ok(context
+        .state
+        .docker
+        .stats(name, options)
+        .map_ok(ContainerStats::from)
+        .map_err(|err| error::Error::BollardError {
+            msg: String::from("Could not follow container stats"),
+            source: err,
+        }))
+}
@@ -0,0 +1,229 @@
+use futures::future;
+use futures::stream::SplitStream;
+use futures::{SinkExt, StreamExt};
+use juniper::http::{GraphQLRequest, GraphQLResponse};
+use juniper::SubscriptionCoordinator;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use slog::{info, warn};
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use warp::ws::{Message, WebSocket};
+
+use crate::api::gql::{bearer_token, Context, Coordinator};
+use crate::error;
+use crate::state::State;
+
+/// The messages of the graphql-ws protocol sent by clients.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    ConnectionInit {
+        #[serde(default)]
+        payload: Option<InitPayload>,
+    },
+    Start {
+        id: String,
+        payload: GraphQLRequest,
+    },
+    Stop {
+        id: String,
+    },
+    ConnectionTerminate,
+}
+
+/// The payload of the connection_init message.
+/// Browsers cannot set headers on a websocket, so the token is given here, either as an
+/// 'authorization' value, like the header, or as is.
+#[derive(Debug, Deserialize)]
+struct InitPayload {
+    #[serde(alias = "Authorization")]
+    authorization: Option<String>,
+    token: Option<String>,
+}
+
+impl InitPayload {
+    fn token(self) -> Result<Option<String>, error::Error> {
+        match (self.authorization, self.token) {
+            (Some(authorization), _) => bearer_token(&authorization).map(Some),
+            (None, token) => Ok(token),
+        }
+    }
+}
+
+/// Serve the subscriptions of a websocket, following the graphql-ws protocol.
+/// The connection is authenticated by the token of the connection_init message, or else
+/// the one of the upgrade request, and closed as soon as this token expires, or is
+/// revoked.
+pub async fn run_session(
+    websocket: WebSocket,
+    coordinator: Arc<Coordinator>,
+    state: State,
+    token: Option<String>,
+) {
+    let logger = state.logger.clone();
+    match serve(websocket, coordinator, state, token).await {
+        Ok(()) => info!(logger, "Subscriptions websocket closed"),
+        Err(err) => warn!(logger, "Subscriptions websocket closed: {}", err),
+    }
+}
+
+async fn serve(
+    websocket: WebSocket,
+    coordinator: Arc<Coordinator>,
+    state: State,
+    token: Option<String>,
+) -> Result<(), error::Error> {
+    let (mut ws_tx, mut ws_rx) = websocket.split();
+
+    // The messages to the client are sent by the subscriptions, each running in its own
+    // task, so they go through a channel. Once all the senders are gone, the websocket
+    // is closed.
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if ws_tx.send(Message::text(msg.to_string())).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_tx.close().await;
+    });
+
+    let context = match next_message(&mut ws_rx).await? {
+        Some(ClientMessage::ConnectionInit { payload }) => {
+            let context = match payload.map_or(Ok(None), InitPayload::token) {
+                Ok(init_token) => Context::new(state, init_token.or(token)).await,
+                Err(err) => Err(err),
+            };
+            match context {
+                Ok(context) => {
+                    let _ = tx.send(json!({ "type": "connection_ack" }));
+                    Arc::new(context)
+                }
+                Err(err) => {
+                    let _ = tx.send(connection_error(&err));
+                    return Err(err);
+                }
+            }
+        }
+        Some(_) => {
+            let err = error::Error::MiscError {
+                msg: String::from("Expected connection_init"),
+            };
+            let _ = tx.send(connection_error(&err));
+            return Err(err);
+        }
+        None => return Ok(()),
+    };
+
+    // Dropping the sender of a subscription stops it.
+    let mut subscriptions: HashMap<String, oneshot::Sender<()>> = HashMap::new();
+
+    let invalidated = context.invalidated();
+    futures::pin_mut!(invalidated);
+
+    loop {
+        tokio::select! {
+            err = &mut invalidated => {
+                let _ = tx.send(connection_error(&err));
+                return Err(err);
+            }
+            msg = next_message(&mut ws_rx) => {
+                let msg = match msg? {
+                    Some(msg) => msg,
+                    None => return Ok(()),
+                };
+
+                if let Err(err) = context.check_token().await {
+                    let _ = tx.send(connection_error(&err));
+                    return Err(err);
+                }
+
+                match msg {
+                    ClientMessage::ConnectionInit { .. } => {}
+                    ClientMessage::Start { id, payload } => {
+                        let (stop, stopped) = oneshot::channel();
+                        // Starting a subscription with the id of another stops the latter.
+                        subscriptions.insert(id.clone(), stop);
+                        tokio::spawn(subscribe(
+                            id,
+                            payload,
+                            Arc::clone(&coordinator),
+                            Arc::clone(&context),
+                            tx.clone(),
+                            stopped,
+                        ));
+                    }
+                    ClientMessage::Stop { id } => {
+                        subscriptions.remove(&id);
+                    }
+                    ClientMessage::ConnectionTerminate => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Run a subscription, sending its results to the client, until it completes or is
+/// stopped.
+async fn subscribe(
+    id: String,
+    request: GraphQLRequest,
+    coordinator: Arc<Coordinator>,
+    context: Arc<Context>,
+    tx: mpsc::UnboundedSender<Value>,
+    stopped: oneshot::Receiver<()>,
+) {
+    let forward = async {
+        match coordinator.subscribe(&request, &context).await {
+            Ok(stream) => {
+                futures::pin_mut!(stream);
+                while let Some(response) = stream.next().await {
+                    let payload = serde_json::to_value(&response).unwrap_or(Value::Null);
+                    let msg = json!({ "type": "data", "id": id, "payload": payload });
+                    if tx.send(msg).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                let response = GraphQLResponse::from_result(Err(err));
+                let payload = serde_json::to_value(&response).unwrap_or(Value::Null);
+                let _ = tx.send(json!({ "type": "error", "id": id, "payload": payload }));
+            }
+        }
+        let _ = tx.send(json!({ "type": "complete", "id": id }));
+    };
+    futures::pin_mut!(forward);
+
+    let _ = future::select(forward, stopped).await;
+}
+
+/// The next message of the client, or nothing once the websocket is closed.
+/// Frames which are not text, such as pings, are skipped.
+async fn next_message(
+    ws_rx: &mut SplitStream<WebSocket>,
+) -> Result<Option<ClientMessage>, error::Error> {
+    while let Some(msg) = ws_rx.next().await {
+        let msg = msg.map_err(|err| error::Error::MiscError {
+            msg: format!("Could not read from websocket: {}", err),
+        })?;
+        if msg.is_close() {
+            return Ok(None);
+        }
+        if let Ok(text) = msg.to_str() {
+            return serde_json::from_str(text)
+                .map(Some)
+                .context(error::JSONError {
+                    msg: String::from("Could not deserialize graphql-ws message"),
+                });
+        }
+    }
+    Ok(None)
+}
+
+fn connection_error(err: &error::Error) -> Value {
+    json!({ "type": "connection_error", "payload": { "message": err.to_string() } })
+}
@@ -0,0 +1,552 @@
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use slog::info;
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::api::environments::{provision_environment, SingleEnvironmentResponseBody};
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::api::spec::EnvironmentSpec;
+use crate::db::model::ProvideData;
+use crate::db::Db;
+use crate::error;
+
+/// The response body for single template
+/// It is optional, since we may be looking for a template which
+/// does not match the query criteria.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleTemplateResponseBody {
+    pub template: Option<Template>,
+}
+
+impl From<Template> for SingleTemplateResponseBody {
+    fn from(template: Template) -> Self {
+        Self {
+            template: Some(template),
+        }
+    }
+}
+
+/// The response body for multiple templates
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiTemplatesResponseBody {
+    pub templates: Vec<Template>,
+    pub templates_count: i32,
+}
+
+impl From<Vec<Template>> for MultiTemplatesResponseBody {
+    fn from(templates: Vec<Template>) -> Self {
+        let templates_count = i32::try_from(templates.len()).unwrap();
+        Self {
+            templates,
+            templates_count,
+        }
+    }
+}
+
+/// The query body for creating or updating a template.
+/// The spec is a compose-like YAML document, in which '${NAME}' is replaced by the
+/// value of the parameter NAME when the template is instantiated, and '${NAME:-default}'
+/// by 'default' if no value is given. '$$' stands for a literal '$'.
+/// The environment name in the spec should use a parameter, so that the template can be
+/// instantiated more than once.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateRequestBody {
+    pub name: String,
+    pub description: Option<String>,
+    pub spec: String,
+}
+
+/// The value given to a template parameter
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct ParameterRequestBody {
+    pub name: String,
+    pub value: String,
+}
+
+/// Retrieve all templates
+pub async fn list_templates(context: &Context) -> Result<MultiTemplatesResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entities = tx
+            .get_all_templates()
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get all them templates",
+            })?;
+
+        let templates = entities.into_iter().map(Template::from).collect::<Vec<_>>();
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(MultiTemplatesResponseBody::from(templates))
+    }
+    .await
+}
+
+/// Retrieve a single template given its name
+pub async fn find_template_by_name(
+    name: &str,
+    context: &Context,
+) -> Result<SingleTemplateResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::get_template_by_name(&mut tx as &mut sqlx::PgConnection, name)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not get template by name",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        Ok(SingleTemplateResponseBody {
+            template: entity.map(Template::from),
+        })
+    }
+    .await
+}
+
+/// Store a new template.
+pub async fn create_template(
+    template_request: TemplateRequestBody,
+    context: &Context,
+) -> Result<SingleTemplateResponseBody, error::Error> {
+    async move {
+        let parameters = check_template(&template_request.spec)?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::create_template(
+            &mut tx as &mut sqlx::PgConnection,
+            &template_request.name,
+            template_request.description.as_deref(),
+            &template_request.spec,
+            &parameters,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create template",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit template creation transaction",
+        })?;
+
+        Ok(SingleTemplateResponseBody::from(Template::from(entity)))
+    }
+    .await
+}
+
+/// Replace the description and the spec of an existing template.
+pub async fn update_template(
+    template_request: TemplateRequestBody,
+    context: &Context,
+) -> Result<SingleTemplateResponseBody, error::Error> {
+    async move {
+        let parameters = check_template(&template_request.spec)?;
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::update_template(
+            &mut tx as &mut sqlx::PgConnection,
+            &template_request.name,
+            template_request.description.as_deref(),
+            &template_request.spec,
+            &parameters,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not update template",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit template update transaction",
+        })?;
+
+        Ok(SingleTemplateResponseBody {
+            template: entity.map(Template::from),
+        })
+    }
+    .await
+}
+
+/// Delete a template. Environments instantiated from the template are left untouched.
+pub async fn delete_template(
+    name: &str,
+    context: &Context,
+) -> Result<SingleTemplateResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity = ProvideData::delete_template_by_name(&mut tx as &mut sqlx::PgConnection, name)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not delete template",
+            })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit template deletion transaction",
+        })?;
+
+        Ok(SingleTemplateResponseBody {
+            template: entity.map(Template::from),
+        })
+    }
+    .await
+}
+
+/// Create an environment from a template, replacing its placeholders with the given
+/// parameters.
+/// The environment expires after the time to live (in seconds), if one is given.
+pub async fn instantiate_template(
+    name: &str,
+    params: Vec<ParameterRequestBody>,
+    owner: Option<String>,
+    ttl: Option<i32>,
+    context: &Context,
+) -> Result<SingleEnvironmentResponseBody, error::Error> {
+    async move {
+        let template = find_template_by_name(name, context)
+            .await?
+            .template
+            .ok_or_else(|| error::Error::MiscError {
+                msg: format!("Template {} does not exist", name),
+            })?;
+
+        let yaml = resolve(name, &template.spec, &template.parameters, params)?;
+
+        info!(context.state.logger, "Instantiating template {}", name);
+
+        let mut spec = EnvironmentSpec::parse(&yaml, owner)?;
+        spec.environment.ttl = ttl;
+
+        provision_environment(spec.environment, &spec.await_healthy, context).await
+    }
+    .await
+}
+
+/// Render a template spec with the given parameter values, falling back on the defaults
+/// of the spec.
+/// Values are inserted as is in the YAML document, so those which could change its
+/// structure, rather than just a scalar, are rejected.
+fn resolve(
+    name: &str,
+    spec: &str,
+    parameters: &[String],
+    params: Vec<ParameterRequestBody>,
+) -> Result<String, error::Error> {
+    let mut values = HashMap::new();
+    for param in params {
+        if !parameters.contains(&param.name) {
+            return Err(error::Error::SpecError {
+                msg: format!("Template {} has no parameter '{}'", name, param.name),
+            });
+        }
+        check_value(&param.name, &param.value)?;
+        values.insert(param.name, param.value);
+    }
+
+    render(spec, |parameter, default| {
+        values
+            .get(parameter)
+            .map(String::as_str)
+            .or(default)
+            .map(String::from)
+            .ok_or_else(|| error::Error::SpecError {
+                msg: format!("Missing value for parameter '{}'", parameter),
+            })
+    })
+}
+
+/// Make sure a parameter value cannot be anything but (part of) a plain YAML scalar:
+/// control characters, such as new lines, could add keys to the document, and YAML
+/// indicators could turn the value into a mapping, a sequence, a reference, or a quoted
+/// string ending early.
+fn check_value(parameter: &str, value: &str) -> Result<(), error::Error> {
+    const INDICATORS: &[char] = &[
+        '{', '}', '[', ']', ',', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+    ];
+
+    let invalid = if value.chars().any(char::is_control) {
+        Some("a control character")
+    } else if value.contains(INDICATORS) {
+        Some("a YAML indicator")
+    } else if value.contains(": ") || value.ends_with(':') {
+        Some("a key separator")
+    } else if value.starts_with(|c: char| c == '-' || c == '?' || c.is_whitespace()) {
+        Some("a leading indicator")
+    } else {
+        None
+    };
+
+    match invalid {
+        Some(reason) => Err(error::Error::ValidationError {
+            field: format!("value of parameter '{}'", parameter),
+            msg: format!("it contains {}", reason),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Make sure a template spec can be instantiated: it must render, and, when all its
+/// parameters have a default value, parse as an environment spec with these defaults.
+fn check_template(spec: &str) -> Result<Vec<String>, error::Error> {
+    let parameters = parameters(spec)?;
+
+    let mut complete = true;
+    let yaml = render(spec, |_, default| {
+        complete &= default.is_some();
+        Ok(String::from(default.unwrap_or_default()))
+    })?;
+
+    if complete {
+        EnvironmentSpec::parse(&yaml, None)?;
+    }
+
+    Ok(parameters)
+}
+
+/// The names of the placeholders in a template spec, in order of first appearance.
+fn parameters(spec: &str) -> Result<Vec<String>, error::Error> {
+    let mut parameters: Vec<String> = Vec::new();
+    render(spec, |parameter, _| {
+        if !parameters.iter().any(|p| p == parameter) {
+            parameters.push(String::from(parameter));
+        }
+        Ok(String::new())
+    })?;
+    Ok(parameters)
+}
+
+/// Replace the placeholders of a template spec by the values returned by `resolve`, which
+/// is given the name of the parameter and its default value, if any.
+fn render<F>(spec: &str, mut resolve: F) -> Result<String, error::Error>
+where
+    F: FnMut(&str, Option<&str>) -> Result<String, error::Error>,
+{
+    let mut output = String::with_capacity(spec.len());
+    let mut rest = spec;
+
+    while let Some(idx) = rest.find('$') {
+        output.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        if let Some(stripped) = rest.strip_prefix('$') {
+            output.push('$');
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('{') {
+            let end = stripped.find('}').ok_or_else(|| error::Error::SpecError {
+                msg: String::from("Unterminated placeholder in template"),
+            })?;
+            let placeholder = &stripped[..end];
+            let (parameter, default) = match placeholder.find(":-") {
+                Some(idx) => (&placeholder[..idx], Some(&placeholder[idx + 2..])),
+                None => (placeholder, None),
+            };
+            if !is_parameter_name(parameter) {
+                return Err(error::Error::SpecError {
+                    msg: format!("Invalid parameter name '{}' in template", parameter),
+                });
+            }
+            output.push_str(&resolve(parameter, default)?);
+            rest = &stripped[end + 1..];
+        } else {
+            output.push('$');
+        }
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn is_parameter_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, value: &str) -> ParameterRequestBody {
+        ParameterRequestBody {
+            name: String::from(name),
+            value: String::from(value),
+        }
+    }
+
+    fn render_with(spec: &str, values: &[(&str, &str)]) -> Result<String, error::Error> {
+        render(spec, |parameter, default| {
+            values
+                .iter()
+                .find(|(name, _)| *name == parameter)
+                .map(|(_, value)| *value)
+                .or(default)
+                .map(String::from)
+                .ok_or_else(|| error::Error::SpecError {
+                    msg: format!("Missing value for parameter '{}'", parameter),
+                })
+        })
+    }
+
+    #[test]
+    fn render_replaces_placeholders() {
+        assert_eq!(
+            render_with(
+                "name: ${ENV}\nimage: postgres:${TAG}",
+                &[("ENV", "dev"), ("TAG", "12")]
+            )
+            .unwrap(),
+            "name: dev\nimage: postgres:12"
+        );
+    }
+
+    #[test]
+    fn render_uses_defaults() {
+        assert_eq!(render_with("tag: ${TAG:-12}", &[]).unwrap(), "tag: 12");
+        assert_eq!(
+            render_with("tag: ${TAG:-12}", &[("TAG", "13")]).unwrap(),
+            "tag: 13"
+        );
+        assert_eq!(render_with("tag: ${TAG:-}", &[]).unwrap(), "tag: ");
+    }
+
+    #[test]
+    fn render_escapes_dollars() {
+        assert_eq!(
+            render_with("cmd: echo $$HOME", &[]).unwrap(),
+            "cmd: echo $HOME"
+        );
+        assert_eq!(render_with("cost: 5$", &[]).unwrap(), "cost: 5$");
+        assert_eq!(render_with("$$${A}", &[("A", "x")]).unwrap(), "$x");
+    }
+
+    #[test]
+    fn render_rejects_invalid_placeholders() {
+        assert!(render_with("name: ${ENV", &[("ENV", "dev")]).is_err());
+        assert!(render_with("name: ${1ENV}", &[("1ENV", "dev")]).is_err());
+        assert!(render_with("name: ${}", &[]).is_err());
+        assert!(render_with("name: ${ENV}", &[]).is_err());
+    }
+
+    #[test]
+    fn parameters_in_order_of_appearance() {
+        assert_eq!(
+            parameters("${B} ${A:-x} ${B} $${C}").unwrap(),
+            vec![String::from("B"), String::from("A")]
+        );
+    }
+
+    #[test]
+    fn resolve_checks_the_parameters() {
+        let parameters = vec![String::from("ENV"), String::from("TAG")];
+        let spec = "name: ${ENV}\ntag: ${TAG:-12}";
+
+        assert_eq!(
+            resolve("t", spec, &parameters, vec![param("ENV", "dev")]).unwrap(),
+            "name: dev\ntag: 12"
+        );
+        assert!(resolve("t", spec, &parameters, vec![]).is_err());
+        assert!(resolve(
+            "t",
+            spec,
+            &parameters,
+            vec![param("ENV", "dev"), param("OTHER", "x")]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn resolve_rejects_injections() {
+        let parameters = vec![String::from("ENV")];
+        let spec = "name: ${ENV}";
+
+        for value in &[
+            "dev\nservices:",
+            "dev\r\n  privileged: true",
+            "dev\tx",
+            "{privileged: true}",
+            "[a, b]",
+            "dev, privileged: true",
+            "dev: x",
+            "dev:",
+            "*alias",
+            "!!binary x",
+            "\"dev",
+            "dev # comment",
+            "- dev",
+            " dev",
+        ] {
+            assert!(
+                resolve("t", spec, &parameters, vec![param("ENV", value)]).is_err(),
+                "{:?} should be rejected",
+                value
+            );
+        }
+
+        for value in &["dev", "my-env_2", "postgres:12", "a.b/c", "http://x"] {
+            assert!(
+                resolve("t", spec, &parameters, vec![param("ENV", value)]).is_ok(),
+                "{:?} should be accepted",
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use bollard::exec::{CreateExecOptions, ResizeExecOptions};
+use futures::stream::SplitStream;
+use futures::{SinkExt, StreamExt};
+use hyper::header::{CONNECTION, CONTENT_TYPE, UPGRADE};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Method, StatusCode};
+use serde::Deserialize;
+use slog::{info, warn};
+use snafu::ResultExt;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use warp::ws::{Message, WebSocket};
+
+use crate::api::containers::find_owned_container;
+use crate::api::engine;
+use crate::api::gql::Context;
+use crate::auth::Role;
+use crate::error;
+use crate::state::State;
+
+/// The parameters of an interactive session, given in the query string.
+/// The command defaults to '/bin/sh', and a TTY is allocated unless told otherwise.
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    pub cmd: Option<String>,
+    pub tty: Option<bool>,
+}
+
+/// The messages a client sends as text frames.
+/// Binary frames are forwarded as is to the session's stdin.
+/// Browsers cannot set headers on a websocket, so unless the upgrade request carries a
+/// token, the first message must be 'authenticate', with the token.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ClientMessage {
+    Authenticate { token: String },
+    Input { data: String },
+    Resize { width: u16, height: u16 },
+}
+
+/// Run an interactive session in a managed container, relaying the websocket
+/// to the session's stdin, and the session's output to the websocket.
+/// The session is closed as soon as the token of the user expires, or is revoked.
+pub async fn run_session(
+    websocket: WebSocket,
+    name: String,
+    query: SessionQuery,
+    state: State,
+    token: Option<String>,
+) {
+    let logger = state.logger.clone();
+    info!(logger, "Opening terminal session on {}", name);
+    match relay(websocket, &name, query, state, token).await {
+        Ok(()) => info!(logger, "Terminal session on {} closed", name),
+        Err(err) => warn!(logger, "Terminal session on {} failed: {}", name, err),
+    }
+}
+
+async fn relay(
+    websocket: WebSocket,
+    name: &str,
+    query: SessionQuery,
+    state: State,
+    token: Option<String>,
+) -> Result<(), error::Error> {
+    let (mut ws_tx, mut ws_rx) = websocket.split();
+
+    let context = match authenticate(&mut ws_rx, state, token).await {
+        Ok(context) => context,
+        Err(err) => {
+            let _ = ws_tx.send(Message::text(format!("{}", err))).await;
+            return Err(err);
+        }
+    };
+    let context = &context;
+
+    let tty = query.tty.unwrap_or(true);
+    let cmd = query
+        .cmd
+        .map(|cmd| cmd.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .unwrap_or_else(|| vec![String::from("/bin/sh")]);
+
+    let (exec_id, upgraded) = match open_session(name, cmd, tty, context).await {
+        Ok(session) => session,
+        Err(err) => {
+            // We let the client know why the session is closed.
+            let _ = ws_tx.send(Message::text(format!("{}", err))).await;
+            return Err(err);
+        }
+    };
+
+    let (mut reader, mut writer) = tokio::io::split(upgraded);
+
+    let output = async {
+        let mut buf = vec![0u8; 4096];
+        let mut frames = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).await.context(error::TokioIOError {
+                msg: String::from("Could not read session output"),
+            })?;
+            if n == 0 {
+                break;
+            }
+            let data = if tty {
+                buf[..n].to_vec()
+            } else {
+                frames.extend_from_slice(&buf[..n]);
+                demultiplex(&mut frames)
+            };
+            if !data.is_empty() {
+                ws_tx
+                    .send(Message::binary(data))
+                    .await
+                    .map_err(|err| error::Error::MiscError {
+                        msg: format!("Could not write to websocket: {}", err),
+                    })?;
+            }
+        }
+        let _ = ws_tx.close().await;
+        Ok::<_, error::Error>(())
+    };
+
+    let input = async {
+        while let Some(Ok(msg)) = ws_rx.next().await {
+            if msg.is_close() {
+                break;
+            }
+            let data = if msg.is_binary() {
+                msg.as_bytes().to_vec()
+            } else if let Ok(text) = msg.to_str() {
+                let msg =
+                    serde_json::from_str::<ClientMessage>(text).context(error::JSONError {
+                        msg: String::from("Could not deserialize terminal message"),
+                    })?;
+                match msg {
+                    ClientMessage::Authenticate { .. } => continue,
+                    ClientMessage::Input { data } => data.into_bytes(),
+                    ClientMessage::Resize { width, height } => {
+                        context
+                            .state
+                            .docker
+                            .resize_exec(&exec_id, ResizeExecOptions { height, width })
+                            .await
+                            .context(error::BollardError {
+                                msg: "Could not resize terminal",
+                            })?;
+                        continue;
+                    }
+                }
+            } else {
+                continue;
+            };
+            writer.write_all(&data).await.context(error::TokioIOError {
+                msg: String::from("Could not write session input"),
+            })?;
+        }
+        Ok::<_, error::Error>(())
+    };
+
+    let res = {
+        let invalidated = context.invalidated();
+
+        futures::pin_mut!(output);
+        futures::pin_mut!(input);
+        futures::pin_mut!(invalidated);
+
+        // The session ends as soon as either the process exits, the client leaves, or its
+        // token is no longer valid.
+        tokio::select! {
+            res = output => res,
+            res = input => res,
+            err = invalidated => Err(err),
+        }
+    };
+
+    // Closing the session's stdin, and the connection, hangs up its process, which
+    // would otherwise be left running in the container.
+    let _ = writer.shutdown().await;
+    drop(reader);
+    drop(writer);
+    close_session(&exec_id, name, &context.state).await;
+
+    res
+}
+
+/// Number of seconds we wait for the process of a session to exit once hung up.
+const CLOSE_TIMEOUT: u64 = 5;
+
+/// Wait for the process of a session to exit.
+/// The docker engine cannot kill an exec instance, so a process which ignores the
+/// hang up is only reported.
+async fn close_session(exec_id: &str, name: &str, state: &State) {
+    for _ in 0..CLOSE_TIMEOUT {
+        match state.docker.inspect_exec(exec_id).await {
+            Ok(exec) if exec.running == Some(true) => {
+                tokio::time::delay_for(Duration::from_secs(1)).await
+            }
+            Ok(_) => return,
+            Err(err) => {
+                warn!(
+                    state.logger,
+                    "Could not inspect terminal session on {}: {}", name, err
+                );
+                return;
+            }
+        }
+    }
+    warn!(
+        state.logger,
+        "Terminal session {} on {} is still running after the client left", exec_id, name
+    );
+}
+
+/// Authenticate the session with the token of the upgrade request, or else the one of
+/// the first message.
+async fn authenticate(
+    ws_rx: &mut SplitStream<WebSocket>,
+    state: State,
+    token: Option<String>,
+) -> Result<Context, error::Error> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let msg = ws_rx.next().await.and_then(Result::ok).and_then(|msg| {
+                msg.to_str()
+                    .ok()
+                    .and_then(|text| serde_json::from_str::<ClientMessage>(text).ok())
+            });
+            match msg {
+                Some(ClientMessage::Authenticate { token }) => token,
+                _ => {
+                    return Err(error::Error::Unauthenticated {
+                        msg: String::from("Expected an authenticate message"),
+                    })
+                }
+            }
+        }
+    };
+
+    Context::new(state, Some(token)).await
+}
+
+/// Create an exec instance in the container, and attach to it.
+/// bollard does not let us write to the stdin of an exec instance, so we start it
+/// ourselves, asking the docker engine to upgrade the connection to a raw stream.
+async fn open_session(
+    name: &str,
+    cmd: Vec<String>,
+    tty: bool,
+    context: &Context,
+) -> Result<(String, Upgraded), error::Error> {
+    context.require(Role::Developer)?;
+
+    find_owned_container(name, context).await?;
+
+    let options = CreateExecOptions {
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        tty: Some(tty),
+        cmd: Some(cmd),
+        ..Default::default()
+    };
+
+    let exec = context
+        .state
+        .docker
+        .create_exec(name, options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not create exec",
+        })?;
+
+    let mut sender = engine::connect(&context.state).await?;
+
+    let body = serde_json::json!({ "Detach": false, "Tty": tty }).to_string();
+    let request = engine::request(Method::POST, &format!("/exec/{}/start", exec.id))
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONNECTION, "Upgrade")
+        .header(UPGRADE, "tcp")
+        .body(Body::from(body))
+        .map_err(|err| error::Error::MiscError {
+            msg: format!("Could not build exec start request: {}", err),
+        })?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .context(error::HyperError {
+            msg: String::from("Could not start exec"),
+        })?;
+
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(error::Error::MiscError {
+            msg: format!("Could not attach to exec: {}", response.status()),
+        });
+    }
+
+    let upgraded = response
+        .into_body()
+        .on_upgrade()
+        .await
+        .context(error::HyperError {
+            msg: String::from("Could not upgrade connection to docker engine"),
+        })?;
+
+    Ok((exec.id, upgraded))
+}
+
+/// Extract the payloads of the frames multiplexed by the docker engine when the
+/// session has no TTY. Each frame has an 8 bytes header, the last 4 being the
+/// payload size. Incomplete frames are left in the buffer.
+fn demultiplex(buffer: &mut Vec<u8>) -> Vec<u8> {
+    let mut data = Vec::new();
+    while buffer.len() >= 8 {
+        let size = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+        if buffer.len() < 8 + size {
+            break;
+        }
+        data.extend_from_slice(&buffer[8..8 + size]);
+        buffer.drain(..8 + size);
+    }
+    data
+}
@@ -1,18 +1,21 @@
+use chrono::Utc;
 use futures::TryFutureExt;
 use juniper::{GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
-use slog::info;
+use slog::{info, warn};
 use snafu::ResultExt;
 use sqlx::Connection;
 use std::convert::TryFrom;
 
 use crate::api::gql::Context;
 use crate::api::model::*;
-use crate::auth;
+use crate::auth::{self, Role};
 use crate::db::model::ProvideAuthn;
 use crate::db::model::ProvideData;
+use crate::db::model::UserEntity;
 use crate::db::Db;
 use crate::error;
+use crate::state::State;
 // use crate::state::{argon, jwt};
 // use crate::fsm;
 
@@ -31,23 +34,36 @@ impl From<User> for SingleUserResponseBody {
     }
 }
 
-/// The response body for a user login
+/// The response body for a user login, or a token refresh
 #[derive(Debug, Deserialize, Serialize, GraphQLObject)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthenticatedUserResponseBody {
     pub user: User,
+    /// The short lived access token, to authenticate the following requests
     pub token: String,
+    /// The token used to get a new access token, once. It is replaced by a new one
+    /// each time it is used.
+    pub refresh_token: String,
 }
 
-impl From<(User, String)> for AuthenticatedUserResponseBody {
-    fn from(auth: (User, String)) -> Self {
+impl From<(User, String, String)> for AuthenticatedUserResponseBody {
+    fn from(auth: (User, String, String)) -> Self {
         Self {
             user: auth.0,
             token: auth.1,
+            refresh_token: auth.2,
         }
     }
 }
 
+/// The response body for a user logout
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct LogoutResponseBody {
+    /// The number of refresh tokens revoked
+    pub refresh_tokens_revoked: i32,
+}
+
 /// The response body for multiple users
 #[derive(Debug, Deserialize, Serialize, GraphQLObject)]
 #[serde(rename_all = "camelCase")]
@@ -107,51 +123,15 @@ pub async fn list_users(context: &Context) -> Result<MultiUsersResponseBody, err
 }
 
 /// Create a new user.
+/// This is the same thing as registering the user, the password is hashed the same way.
 pub async fn add_user(
     user_request: UserRequestBody,
     context: &Context,
 ) -> Result<SingleUserResponseBody, error::Error> {
-    async move {
-        let UserRequestBody {
-            username,
-            email,
-            password,
-        } = user_request;
-
-        let pool = &context.state.pool;
-
-        let mut tx = pool
-            .conn()
-            .and_then(Connection::begin)
-            .await
-            .context(error::DBError {
-                msg: "could not initiate transaction",
-            })?;
-
-        let entity = ProvideData::create_user(
-            &mut tx as &mut sqlx::PgConnection,
-            &username,
-            &email,
-            &password,
-        )
-        .await
-        .context(error::DBProvideError {
-            msg: "Could not create user",
-        })?;
-
-        let user = User::from(entity);
-
-        tx.commit().await.context(error::DBError {
-            msg: "could not retrieve indexes",
-        })?;
-
-        Ok(SingleUserResponseBody::from(user))
-    }
-    .await
+    register_user(user_request, context).await
 }
 
-/// Register a new user.
-/// This is really the same thing as new user...
+/// Register a new user, with a hashed password.
 pub async fn register_user(
     user_request: UserRequestBody,
     context: &Context,
@@ -250,14 +230,154 @@ pub async fn find_user_by_username(
     .await
 }
 
+/// Replace the roles of a user. The new roles apply to the tokens issued from now on.
+pub async fn set_user_roles(
+    username: &str,
+    roles: &[Role],
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        let roles = roles
+            .iter()
+            .map(|role| String::from(role.as_str()))
+            .collect::<Vec<_>>();
+
+        info!(
+            context.state.logger,
+            "Setting roles of user {} to {:?}", username, roles
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity =
+            ProvideData::update_user_roles(&mut tx as &mut sqlx::PgConnection, username, &roles)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not update user roles",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit user roles transaction",
+        })?;
+
+        Ok(SingleUserResponseBody {
+            user: entity.map(User::from),
+        })
+    }
+    .await
+}
+
+/// Grant the admin role to the given users, keeping their other roles.
+/// This is how the first admin of a deployment is made, so it runs when the service
+/// starts. Users who did not register yet are skipped.
+pub async fn promote_admins(usernames: &[String], state: &State) -> Result<(), error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    for username in usernames {
+        let entity =
+            ProvideData::get_user_by_username(&mut tx as &mut sqlx::PgConnection, username)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by username",
+                })?;
+
+        let mut roles = match entity {
+            Some(entity) => entity.roles,
+            None => {
+                warn!(
+                    state.logger,
+                    "Admin {} is not registered, not granting the admin role", username
+                );
+                continue;
+            }
+        };
+
+        if roles.iter().any(|role| role == Role::Admin.as_str()) {
+            continue;
+        }
+
+        info!(state.logger, "Granting the admin role to {}", username);
+        roles.push(String::from(Role::Admin.as_str()));
+
+        ProvideData::update_user_roles(&mut tx as &mut sqlx::PgConnection, username, &roles)
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not update user roles",
+            })?;
+    }
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit admin roles transaction",
+    })?;
+
+    Ok(())
+}
+
+/// Replace the teams a user belongs to.
+pub async fn set_user_teams(
+    username: &str,
+    teams: &[String],
+    context: &Context,
+) -> Result<SingleUserResponseBody, error::Error> {
+    async move {
+        info!(
+            context.state.logger,
+            "Setting teams of user {} to {:?}", username, teams
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let entity =
+            ProvideData::update_user_teams(&mut tx as &mut sqlx::PgConnection, username, teams)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not update user teams",
+                })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit user teams transaction",
+        })?;
+
+        Ok(SingleUserResponseBody {
+            user: entity.map(User::from),
+        })
+    }
+    .await
+}
+
 /// user login
+/// Unknown users, inactive users, and wrong passwords all get the same error, so that
+/// callers cannot find out which usernames exist.
 pub async fn login_user(
     credentials: CredentialsRequestBody,
     context: &Context,
 ) -> Result<AuthenticatedUserResponseBody, error::Error> {
     async move {
         // First we lookup an account based on the username
-        // If there is no such account, return Ok(None)
         // 2. Compare using password hasher
         //
         // I am not reusing the find_user_by_username function because it
@@ -279,18 +399,22 @@ pub async fn login_user(
                 msg: "Could not get user by username",
             })?;
 
-        if entity.is_none() {
-            info!(context.state.logger, "Cannot find user");
-            return Err(error::Error::MiscError {
-                msg: String::from("Unknown user"),
-            });
-        }
-
-        let entity = entity.unwrap();
         tx.commit().await.context(error::DBError {
             msg: "could not commit transaction",
         })?;
 
+        let invalid_credentials = || error::Error::Unauthenticated {
+            msg: String::from("Invalid credentials"),
+        };
+
+        let entity = match entity {
+            Some(entity) => entity,
+            None => {
+                info!(context.state.logger, "Cannot find user");
+                return Err(invalid_credentials());
+            }
+        };
+
         let is_valid = context
             .state
             .argon
@@ -303,24 +427,314 @@ pub async fn login_user(
             })?;
 
         if !is_valid {
-            return Err(error::Error::MiscError {
-                msg: String::from("Invalid credentials"),
+            return Err(invalid_credentials());
+        }
+
+        if !entity.active {
+            info!(
+                context.state.logger,
+                "Inactive user {} cannot login", entity.username
+            );
+            return Err(invalid_credentials());
+        }
+
+        // User is authenticated, so build the jwt tokens
+        issue_tokens(entity, &context.state).await
+    }
+    .await
+}
+
+/// Exchange a refresh token for a new access token, and a new refresh token.
+/// A refresh token can only be used once: presenting a token which was already used
+/// means it was stolen, and all the refresh tokens of its user are revoked.
+pub async fn refresh_token(
+    refresh_token: &str,
+    context: &Context,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let token =
+            ProvideAuthn::get_refresh_token(&mut tx as &mut sqlx::PgConnection, refresh_token)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get refresh token",
+                })?
+                .ok_or_else(|| error::Error::Unauthenticated {
+                    msg: String::from("Invalid refresh token"),
+                })?;
+
+        if token.revoked_at.is_some() {
+            warn!(
+                context.state.logger,
+                "Refresh token {} reused, revoking all the refresh tokens of user {}",
+                token.id,
+                token.user_id
+            );
+            ProvideAuthn::revoke_user_refresh_tokens(
+                &mut tx as &mut sqlx::PgConnection,
+                token.user_id,
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not revoke refresh tokens",
+            })?;
+            tx.commit().await.context(error::DBError {
+                msg: "could not commit refresh token revocation transaction",
+            })?;
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Refresh token already used"),
+            });
+        }
+
+        if token.expires_at <= Utc::now() {
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Refresh token expired"),
             });
         }
 
-        // User is authenticated, so build the jwt token
-        let claims = auth::PrivateClaims {
-            roles: entity
-                .roles
-                .iter()
-                .map(|role| String::from(role))
-                .collect::<Vec<String>>(),
+        // The user may have been updated since, so the new access token carries the
+        // current roles.
+        let entity =
+            ProvideAuthn::get_user_by_id(&mut tx as &mut sqlx::PgConnection, token.user_id)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get user by id",
+                })?
+                .ok_or_else(|| error::Error::Unauthenticated {
+                    msg: String::from("Unknown user"),
+                })?;
+
+        if !entity.active {
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Inactive user"),
+            });
+        }
+
+        let (new_refresh_token, expires_at) = context.state.jwt.refresh_token();
+
+        let replacement = ProvideAuthn::create_refresh_token(
+            &mut tx as &mut sqlx::PgConnection,
+            entity.id,
+            &new_refresh_token,
+            expires_at,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create refresh token",
+        })?;
+
+        // Another request may have rotated the token in the meantime.
+        ProvideAuthn::revoke_refresh_token(
+            &mut tx as &mut sqlx::PgConnection,
+            token.id,
+            Some(replacement.id),
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not revoke refresh token",
+        })?
+        .ok_or_else(|| error::Error::Unauthenticated {
+            msg: String::from("Refresh token already used"),
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit refresh token transaction",
+        })?;
+
+        let token = access_token(&entity, &context.state)?;
+
+        Ok(AuthenticatedUserResponseBody::from((
+            User::from(entity),
+            token,
+            new_refresh_token,
+        )))
+    }
+    .await
+}
+
+/// Log the user making the request out: its access token is revoked, as well as the
+/// given refresh token, or all its refresh tokens if none is given.
+pub async fn logout_user(
+    refresh_token: Option<String>,
+    context: &Context,
+) -> Result<LogoutResponseBody, error::Error> {
+    async move {
+        let principal = context.principal()?;
+
+        info!(
+            context.state.logger,
+            "Logging out user {}", principal.username
+        );
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        if let (Some(token_id), Some(expires_at)) =
+            (principal.token_id.as_deref(), principal.expires_at)
+        {
+            ProvideAuthn::revoke_token(&mut tx as &mut sqlx::PgConnection, token_id, expires_at)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not revoke access token",
+                })?;
+        }
+
+        // The denylist only needs to hold the tokens which are not expired yet.
+        ProvideAuthn::delete_expired_revoked_tokens(&mut tx as &mut sqlx::PgConnection, Utc::now())
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not delete expired revoked tokens",
+            })?;
+
+        let entity = ProvideData::get_user_by_username(
+            &mut tx as &mut sqlx::PgConnection,
+            &principal.username,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get user by username",
+        })?
+        .ok_or_else(|| error::Error::MiscError {
+            msg: String::from("Unknown user"),
+        })?;
+
+        let revoked = match refresh_token {
+            Some(refresh_token) => {
+                let token = ProvideAuthn::get_refresh_token(
+                    &mut tx as &mut sqlx::PgConnection,
+                    &refresh_token,
+                )
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get refresh token",
+                })?
+                .filter(|token| token.user_id == entity.id)
+                .ok_or_else(|| error::Error::MiscError {
+                    msg: String::from("Invalid refresh token"),
+                })?;
+
+                ProvideAuthn::revoke_refresh_token(
+                    &mut tx as &mut sqlx::PgConnection,
+                    token.id,
+                    None,
+                )
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not revoke refresh token",
+                })?
+                .into_iter()
+                .count()
+            }
+            None => ProvideAuthn::revoke_user_refresh_tokens(
+                &mut tx as &mut sqlx::PgConnection,
+                entity.id,
+            )
+            .await
+            .context(error::DBProvideError {
+                msg: "Could not revoke refresh tokens",
+            })?
+            .len(),
         };
 
-        let user = User::from(entity);
-        let token = context.state.jwt.encode(claims)?;
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit logout transaction",
+        })?;
 
-        Ok(AuthenticatedUserResponseBody::from((user, token)))
+        Ok(LogoutResponseBody {
+            refresh_tokens_revoked: i32::try_from(revoked).unwrap(),
+        })
     }
     .await
 }
+
+/// Whether an access token, given by its id (jti), was revoked before it expired.
+pub async fn is_token_revoked(token_id: &str, state: &State) -> Result<bool, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let revoked = ProvideAuthn::is_token_revoked(&mut tx as &mut sqlx::PgConnection, token_id)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not check token revocation",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(revoked)
+}
+
+/// Issue a new access token, and a new refresh token, for an authenticated user.
+async fn issue_tokens(
+    entity: UserEntity,
+    state: &State,
+) -> Result<AuthenticatedUserResponseBody, error::Error> {
+    let (refresh_token, expires_at) = state.jwt.refresh_token();
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    ProvideAuthn::create_refresh_token(
+        &mut tx as &mut sqlx::PgConnection,
+        entity.id,
+        &refresh_token,
+        expires_at,
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not create refresh token",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit refresh token transaction",
+    })?;
+
+    let token = access_token(&entity, state)?;
+
+    Ok(AuthenticatedUserResponseBody::from((
+        User::from(entity),
+        token,
+        refresh_token,
+    )))
+}
+
+/// The access token of a user, whose subject is the id of the user.
+fn access_token(entity: &UserEntity, state: &State) -> Result<String, error::Error> {
+    let claims = auth::PrivateClaims {
+        username: entity.username.clone(),
+        roles: entity.roles.clone(),
+    };
+
+    state.jwt.encode(&entity.id.to_string(), claims)
+}
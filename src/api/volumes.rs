@@ -0,0 +1,548 @@
+use bollard::volume::{
+    CreateVolumeOptions, ListVolumesOptions, PruneVolumesOptions, RemoveVolumeOptions,
+};
+use futures::TryFutureExt;
+use juniper::{GraphQLInputObject, GraphQLObject};
+use serde::{Deserialize, Serialize};
+use slog::info;
+use snafu::ResultExt;
+use sqlx::Connection;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::default::Default;
+
+use crate::api::containers::MANAGED_LABEL;
+use crate::api::gql::Context;
+use crate::api::model::*;
+use crate::auth::Role;
+use crate::db::model::{EntityId, ProvideData, VolumeEntity};
+use crate::db::Db;
+use crate::error;
+use crate::state::State;
+
+/// The driver used when none is requested
+const DEFAULT_DRIVER: &str = "local";
+
+/// The response body for single volume
+/// It is optional, since we may be looking for a volume which
+/// does not match the query criteria.
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleVolumeResponseBody {
+    pub volume: Option<Volume>,
+}
+
+impl From<Volume> for SingleVolumeResponseBody {
+    fn from(volume: Volume) -> Self {
+        Self {
+            volume: Some(volume),
+        }
+    }
+}
+
+/// The response body for multiple volumes
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiVolumesResponseBody {
+    pub volumes: Vec<Volume>,
+    pub volumes_count: i32,
+}
+
+impl From<Vec<Volume>> for MultiVolumesResponseBody {
+    fn from(volumes: Vec<Volume>) -> Self {
+        let volumes_count = i32::try_from(volumes.len()).unwrap();
+        Self {
+            volumes,
+            volumes_count,
+        }
+    }
+}
+
+/// The response body for a volume pruning
+#[derive(Debug, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneVolumesResponseBody {
+    /// The names of the removed volumes
+    pub volumes: Vec<String>,
+    /// Disk space reclaimed, in bytes
+    pub space_reclaimed: f64,
+}
+
+/// The query body for creating a new volume.
+/// The volume can be attached to an existing environment, given its name.
+#[derive(Debug, Serialize, Deserialize, GraphQLInputObject)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeRequestBody {
+    pub name: String,
+    pub driver: Option<String>,
+    pub environment: Option<String>,
+}
+
+/// Retrieve the volumes visible to the user making the request, with their mountpoint on
+/// the docker host.
+pub async fn list_volumes(context: &Context) -> Result<MultiVolumesResponseBody, error::Error> {
+    async move {
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        // Admins see all the volumes, the others only their own, and the ones of the
+        // environments they can see.
+        let principal = context.principal()?;
+        let entities = if principal.has_role(Role::Admin) {
+            tx.get_all_volumes().await.context(error::DBProvideError {
+                msg: "Could not get all them volumes",
+            })?
+        } else {
+            tx.get_volumes_visible_to(&principal.username)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get visible volumes",
+                })?
+        };
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit transaction",
+        })?;
+
+        let mut filters = HashMap::new();
+        filters.insert("label", vec![MANAGED_LABEL]);
+
+        let resp = context
+            .state
+            .docker
+            .list_volumes(Some(ListVolumesOptions { filters }))
+            .await
+            .context(error::BollardError {
+                msg: "Could not list volumes",
+            })?;
+
+        let mountpoints = resp
+            .volumes
+            .into_iter()
+            .map(|volume| (volume.name, volume.mountpoint))
+            .collect::<HashMap<_, _>>();
+
+        let volumes = entities
+            .into_iter()
+            .map(|entity| {
+                let mountpoint = mountpoints.get(&entity.name).cloned();
+                Volume {
+                    mountpoint,
+                    ..Volume::from(entity)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(MultiVolumesResponseBody::from(volumes))
+    }
+    .await
+}
+
+/// Create a new volume
+pub async fn create_volume(
+    volume_request: VolumeRequestBody,
+    context: &Context,
+) -> Result<SingleVolumeResponseBody, error::Error> {
+    async move {
+        let VolumeRequestBody {
+            name,
+            driver,
+            environment,
+        } = volume_request;
+
+        let driver = driver.unwrap_or_else(|| String::from(DEFAULT_DRIVER));
+
+        // Recording a volume which the docker engine already has would hand it over.
+        if docker_volume_exists(&name, &context.state).await? {
+            return Err(error::Error::Forbidden {
+                msg: format!("Volume {} already exists", name),
+            });
+        }
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        let (environment_id, owner) = match environment {
+            None => (None, context.principal()?.username.clone()),
+            Some(environment) => {
+                let entity = ProvideData::get_environment_by_name(
+                    &mut tx as &mut sqlx::PgConnection,
+                    &environment,
+                )
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not get environment by name",
+                })?
+                .ok_or_else(|| error::Error::MiscError {
+                    msg: format!("Environment {} does not exist", environment),
+                })?;
+                if !context.principal()?.can_manage(&entity.owner) {
+                    return Err(error::Error::Forbidden {
+                        msg: format!(
+                            "Only {} or an admin can attach a volume to environment {}",
+                            entity.owner, environment
+                        ),
+                    });
+                }
+                (Some(entity.id), entity.owner)
+            }
+        };
+
+        let entity = ProvideData::create_volume(
+            &mut tx as &mut sqlx::PgConnection,
+            &name,
+            &driver,
+            environment_id,
+            &owner,
+        )
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not create volume",
+        })?;
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit volume creation transaction",
+        })?;
+
+        // The volume is recorded before being created, so that a duplicate is rejected
+        // without touching the docker engine. We don't keep a transaction open while
+        // waiting for the docker engine, so the record is deleted if the creation fails.
+        let mountpoint = match docker_create_volume(&name, &driver, &context.state).await {
+            Ok(mountpoint) => mountpoint,
+            Err(err) => {
+                delete_volume_record(&name, &context.state).await?;
+                return Err(err);
+            }
+        };
+
+        Ok(SingleVolumeResponseBody::from(Volume {
+            mountpoint: Some(mountpoint),
+            ..Volume::from(entity)
+        }))
+    }
+    .await
+}
+
+/// Delete a volume. This fails if the volume is still mounted by a container.
+pub async fn delete_volume(
+    name: &str,
+    context: &Context,
+) -> Result<SingleVolumeResponseBody, error::Error> {
+    async move {
+        let entity = get_volume_record(name, &context.state).await?;
+
+        if entity.is_none() {
+            info!(
+                context.state.logger,
+                "Volume {} is not managed, not removing", name
+            );
+            return Ok(SingleVolumeResponseBody { volume: None });
+        }
+
+        info!(context.state.logger, "Removing volume {}", name);
+
+        // The record is only deleted once the docker engine removed the volume, which it
+        // refuses to do while the volume is mounted.
+        context
+            .state
+            .docker
+            .remove_volume(name, Some(RemoveVolumeOptions { force: false }))
+            .await
+            .context(error::BollardError {
+                msg: "Could not remove volume",
+            })?;
+
+        let entity = delete_volume_record(name, &context.state).await?;
+
+        Ok(SingleVolumeResponseBody {
+            volume: entity.map(Volume::from),
+        })
+    }
+    .await
+}
+
+/// Delete the record of a volume, returning it if there was one.
+async fn delete_volume_record(
+    name: &str,
+    state: &State,
+) -> Result<Option<VolumeEntity>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::delete_volume_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not delete volume",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit volume deletion transaction",
+    })?;
+
+    Ok(entity)
+}
+
+/// Remove the volumes created by this service which are not mounted by any container.
+pub async fn prune_volumes(context: &Context) -> Result<PruneVolumesResponseBody, error::Error> {
+    async move {
+        let version = context
+            .state
+            .docker
+            .version()
+            .await
+            .context(error::BollardError {
+                msg: "Could not get docker version",
+            })?;
+
+        let mut filters = HashMap::new();
+        filters.insert("label", vec![MANAGED_LABEL]);
+        if prunes_anonymous_volumes_only(&version.api_version) {
+            filters.insert("all", vec!["true"]);
+        }
+
+        let resp = context
+            .state
+            .docker
+            .prune_volumes(Some(PruneVolumesOptions { filters }))
+            .await
+            .context(error::BollardError {
+                msg: "Could not prune volumes",
+            })?;
+
+        let volumes = resp.volumes_deleted.unwrap_or_default();
+
+        let pool = &context.state.pool;
+
+        let mut tx = pool
+            .conn()
+            .and_then(Connection::begin)
+            .await
+            .context(error::DBError {
+                msg: "could not initiate transaction",
+            })?;
+
+        for volume in volumes.iter() {
+            info!(context.state.logger, "Pruned volume {}", volume);
+            ProvideData::delete_volume_by_name(&mut tx as &mut sqlx::PgConnection, volume)
+                .await
+                .context(error::DBProvideError {
+                    msg: "Could not delete volume",
+                })?;
+        }
+
+        tx.commit().await.context(error::DBError {
+            msg: "could not commit volume pruning transaction",
+        })?;
+
+        Ok(PruneVolumesResponseBody {
+            volumes,
+            space_reclaimed: resp.space_reclaimed.unwrap_or_default() as f64,
+        })
+    }
+    .await
+}
+
+/// Since API 1.42, the docker engine only prunes anonymous volumes, unless given the
+/// 'all' filter, which older engines reject. Our volumes are all named.
+fn prunes_anonymous_volumes_only(api_version: &str) -> bool {
+    let mut parts = api_version.split('.').map(|part| part.parse::<u32>().ok());
+    match (parts.next().flatten(), parts.next().flatten()) {
+        (Some(major), Some(minor)) => (major, minor) >= (1, 42),
+        _ => false,
+    }
+}
+
+/// Make sure a named volume mounted by a new container exists, and record it, attached
+/// to the container's environment and owned by the container's owner.
+/// An existing volume is reused as is, so its data survives the recreation of the container,
+/// provided the user making the request can manage it. Volumes which this service did not
+/// create cannot be mounted.
+pub async fn ensure_volume(
+    name: &str,
+    environment_id: Option<EntityId>,
+    owner: &str,
+    context: &Context,
+) -> Result<(), error::Error> {
+    let state = &context.state;
+
+    if let Some(entity) = get_volume_record(name, state).await? {
+        authorize_mount(&entity, context)?;
+        // Creating a volume which already exists is a no-op for the docker engine, and
+        // this recreates a volume removed behind our back.
+        docker_create_volume(name, DEFAULT_DRIVER, state).await?;
+        return Ok(());
+    }
+
+    if docker_volume_exists(name, state).await? {
+        return Err(error::Error::Forbidden {
+            msg: format!("Volume {} is not managed by this service", name),
+        });
+    }
+
+    docker_create_volume(name, DEFAULT_DRIVER, state).await?;
+
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::attach_volume(
+        &mut tx as &mut sqlx::PgConnection,
+        name,
+        DEFAULT_DRIVER,
+        environment_id,
+        owner,
+    )
+    .await
+    .context(error::DBProvideError {
+        msg: "Could not record volume",
+    })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit volume transaction",
+    })?;
+
+    // Another request recorded the volume in the meantime, so it may not be ours.
+    if entity.is_none() {
+        let entity =
+            get_volume_record(name, state)
+                .await?
+                .ok_or_else(|| error::Error::MiscError {
+                    msg: format!("Volume {} was removed while being mounted", name),
+                })?;
+        authorize_mount(&entity, context)?;
+    }
+
+    Ok(())
+}
+
+/// Only the owner of a volume, or an admin, can mount it. Volumes recorded before their
+/// owners were have none, and only admins can mount them.
+fn authorize_mount(entity: &VolumeEntity, context: &Context) -> Result<(), error::Error> {
+    let owner = entity.owner.clone().unwrap_or_default();
+    if context.principal()?.can_manage(&owner) {
+        Ok(())
+    } else {
+        Err(error::Error::Forbidden {
+            msg: format!(
+                "Only the owner of volume {} or an admin can mount it",
+                entity.name
+            ),
+        })
+    }
+}
+
+/// Retrieve the record of a volume, if any.
+async fn get_volume_record(
+    name: &str,
+    state: &State,
+) -> Result<Option<VolumeEntity>, error::Error> {
+    let pool = &state.pool;
+
+    let mut tx = pool
+        .conn()
+        .and_then(Connection::begin)
+        .await
+        .context(error::DBError {
+            msg: "could not initiate transaction",
+        })?;
+
+    let entity = ProvideData::get_volume_by_name(&mut tx as &mut sqlx::PgConnection, name)
+        .await
+        .context(error::DBProvideError {
+            msg: "Could not get volume by name",
+        })?;
+
+    tx.commit().await.context(error::DBError {
+        msg: "could not commit transaction",
+    })?;
+
+    Ok(entity)
+}
+
+/// Check whether the docker engine has a volume with the given name, whoever created it.
+async fn docker_volume_exists(name: &str, state: &State) -> Result<bool, error::Error> {
+    // The docker engine filters volumes by substring of their name.
+    let mut filters = HashMap::new();
+    filters.insert("name", vec![name]);
+
+    let resp = state
+        .docker
+        .list_volumes(Some(ListVolumesOptions { filters }))
+        .await
+        .context(error::BollardError {
+            msg: "Could not list volumes",
+        })?;
+
+    Ok(resp.volumes.iter().any(|volume| volume.name == name))
+}
+
+/// Create a volume in the docker engine, and return its mountpoint.
+async fn docker_create_volume(
+    name: &str,
+    driver: &str,
+    state: &State,
+) -> Result<String, error::Error> {
+    info!(state.logger, "Creating volume {}", name);
+
+    let mut labels = HashMap::new();
+    labels.insert(MANAGED_LABEL, "true");
+
+    let options = CreateVolumeOptions {
+        name,
+        driver,
+        labels,
+        ..Default::default()
+    };
+
+    let volume = state
+        .docker
+        .create_volume(options)
+        .await
+        .context(error::BollardError {
+            msg: "Could not create volume",
+        })?;
+
+    Ok(volume.mountpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_anonymous_volumes_only_since_1_42() {
+        assert!(prunes_anonymous_volumes_only("1.42"));
+        assert!(prunes_anonymous_volumes_only("1.43"));
+        assert!(prunes_anonymous_volumes_only("2.0"));
+        assert!(!prunes_anonymous_volumes_only("1.41"));
+        assert!(!prunes_anonymous_volumes_only("1.9"));
+        assert!(!prunes_anonymous_volumes_only(""));
+    }
+}
@@ -1,6 +1,12 @@
+use biscuit::ClaimsSet;
+use chrono::{DateTime, Utc};
+use juniper::GraphQLEnum;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PrivateClaims {
+    pub username: String,
     pub roles: Vec<String>,
 }
 
@@ -9,3 +15,82 @@ impl PrivateClaims {
         self.roles.to_owned()
     }
 }
+
+/// What a user is allowed to do. Each role includes the permissions of the previous ones:
+/// viewers can look at everything, developers can also create and operate their own
+/// environments, and admins can manage everything, including the others' environments,
+/// the images and the users.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, GraphQLEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Developer,
+    Admin,
+}
+
+impl Role {
+    /// The name used in the database and in the tokens for this role
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Developer => "developer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "viewer" => Ok(Role::Viewer),
+            "developer" => Ok(Role::Developer),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role '{}'", role)),
+        }
+    }
+}
+
+/// The authenticated user of a request, as found in its token.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// The id of the user
+    pub subject: Option<String>,
+    pub username: String,
+    pub roles: Vec<String>,
+    /// The id of the token (jti), used to revoke it
+    pub token_id: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Principal {
+    /// Whether the user has the given role, or a role which includes it.
+    /// Unknown roles are ignored.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles
+            .iter()
+            .filter_map(|name| Role::from_str(name).ok())
+            .any(|granted| granted >= role)
+    }
+
+    /// Whether the user can manage a resource belonging to the given owner: users manage
+    /// their own resources, and admins manage everybody's.
+    pub fn can_manage(&self, owner: &str) -> bool {
+        self.username == owner || self.has_role(Role::Admin)
+    }
+}
+
+impl From<ClaimsSet<PrivateClaims>> for Principal {
+    fn from(claims: ClaimsSet<PrivateClaims>) -> Self {
+        Principal {
+            subject: claims.registered.subject.map(|subject| subject.to_string()),
+            username: claims.private.username,
+            roles: claims.private.roles,
+            token_id: claims.registered.id,
+            expires_at: claims.registered.expiry.map(|expiry| *expiry),
+        }
+    }
+}
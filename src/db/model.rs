@@ -10,10 +10,109 @@ pub struct ContainerEntity {
     pub id: String,
     pub name: String,
     pub image: String,
+    pub spec: ContainerSpec,
+    pub state: String,
+    pub environment_id: Option<EntityId>,
+    /// The user who created the container
+    pub owner: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// A group of containers, created and deleted as a unit.
+#[derive(Debug, Clone)]
+pub struct EnvironmentEntity {
+    pub id: EntityId,
+    pub name: String,
+    pub description: Option<String>,
+    pub owner: String,
+    pub network: Option<String>,
+    /// When the environment is torn down automatically
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The last time the environment was accessed, or its containers were active
+    pub active_at: DateTime<Utc>,
+    /// When the environment was stopped for being idle
+    pub stopped_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An environment shared with a user, or with the members of a team.
+#[derive(Debug, Clone)]
+pub struct ShareEntity {
+    pub environment_id: EntityId,
+    /// Either 'user' or 'team'
+    pub kind: String,
+    pub grantee: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An environment torn down by the expiry scheduler, with the containers removed.
+#[derive(Debug, Clone)]
+pub struct ExpirationEntity {
+    pub id: EntityId,
+    pub environment: String,
+    pub owner: String,
+    pub containers: Vec<String>,
+    pub expired_at: DateTime<Utc>,
+    pub removed_at: DateTime<Utc>,
+}
+
+/// A reusable environment spec, with parameter placeholders (eg '${BRANCH}').
+#[derive(Debug, Clone)]
+pub struct TemplateEntity {
+    pub id: EntityId,
+    pub name: String,
+    pub description: Option<String>,
+    pub spec: String,
+    pub parameters: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A named volume, which outlives the containers mounting it.
+#[derive(Debug, Clone)]
+pub struct VolumeEntity {
+    pub name: String,
+    pub driver: String,
+    pub environment_id: Option<EntityId>,
+    /// The owner of the volume's environment, or the user who created the volume
+    pub owner: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The credentials used to pull images from a private registry.
+/// The password is never read back, except to pull images.
+#[derive(Debug, Clone)]
+pub struct RegistryEntity {
+    pub registry: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Decrypted registry credentials
+#[derive(Clone)]
+pub struct RegistryCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// The configuration a container was started with.
+/// Values are stored in the form the docker engine expects them, eg 'KEY=value' for
+/// environment variables, '8080:80/tcp' for ports, and 'source:target:ro' for mounts.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerSpec {
+    pub env: Vec<String>,
+    pub cmd: Vec<String>,
+    pub entrypoint: Vec<String>,
+    pub ports: Vec<String>,
+    pub mounts: Vec<String>,
+    pub working_dir: Option<String>,
+    pub labels: Vec<String>,
+}
+
 // From sqlx realworld example
 #[async_trait]
 pub trait ProvideData {
@@ -22,10 +121,19 @@ pub trait ProvideData {
         id: &str,
         name: &str,
         image: &str,
+        spec: &ContainerSpec,
+        environment_id: Option<EntityId>,
+        owner: Option<&str>,
     ) -> ProvideResult<ContainerEntity>;
 
     async fn get_all_containers(&mut self) -> ProvideResult<Vec<ContainerEntity>>;
 
+    /// The containers a user created, and the containers of the environments visible to them.
+    async fn get_containers_visible_to(
+        &mut self,
+        username: &str,
+    ) -> ProvideResult<Vec<ContainerEntity>>;
+
     async fn get_container_by_name(
         &mut self,
         username: &str,
@@ -35,6 +143,201 @@ pub trait ProvideData {
         &mut self,
         name: &str,
     ) -> ProvideResult<Option<ContainerEntity>>;
+
+    async fn update_container_state(
+        &mut self,
+        name: &str,
+        state: &str,
+    ) -> ProvideResult<Option<ContainerEntity>>;
+
+    async fn get_containers_by_environment(
+        &mut self,
+        environment_id: EntityId,
+    ) -> ProvideResult<Vec<ContainerEntity>>;
+
+    async fn create_environment(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        owner: &str,
+        network: Option<&str>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> ProvideResult<EnvironmentEntity>;
+
+    async fn get_all_environments(&mut self) -> ProvideResult<Vec<EnvironmentEntity>>;
+
+    /// The environments a user owns, or which are shared with them, directly or with one
+    /// of their teams.
+    async fn get_environments_visible_to(
+        &mut self,
+        username: &str,
+    ) -> ProvideResult<Vec<EnvironmentEntity>>;
+
+    async fn create_environment_share(
+        &mut self,
+        environment_id: EntityId,
+        kind: &str,
+        grantee: &str,
+    ) -> ProvideResult<ShareEntity>;
+
+    async fn delete_environment_share(
+        &mut self,
+        environment_id: EntityId,
+        kind: &str,
+        grantee: &str,
+    ) -> ProvideResult<Option<ShareEntity>>;
+
+    async fn get_environment_shares(
+        &mut self,
+        environment_id: EntityId,
+    ) -> ProvideResult<Vec<ShareEntity>>;
+
+    async fn get_environment_by_name(
+        &mut self,
+        name: &str,
+    ) -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn delete_environment_by_name(
+        &mut self,
+        name: &str,
+    ) -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn update_environment_expiry(
+        &mut self,
+        name: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn get_expired_environments(
+        &mut self,
+        now: DateTime<Utc>,
+    ) -> ProvideResult<Vec<EnvironmentEntity>>;
+
+    async fn touch_environment(&mut self, id: EntityId)
+        -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn update_environment_stopped(
+        &mut self,
+        name: &str,
+        stopped_at: Option<DateTime<Utc>>,
+    ) -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn resume_environment(
+        &mut self,
+        id: EntityId,
+    ) -> ProvideResult<Option<EnvironmentEntity>>;
+
+    async fn create_expiration(
+        &mut self,
+        environment: &str,
+        owner: &str,
+        containers: &[String],
+        expired_at: DateTime<Utc>,
+    ) -> ProvideResult<ExpirationEntity>;
+
+    async fn get_all_expirations(&mut self) -> ProvideResult<Vec<ExpirationEntity>>;
+
+    async fn get_expirations_by_owner(
+        &mut self,
+        owner: &str,
+    ) -> ProvideResult<Vec<ExpirationEntity>>;
+
+    async fn create_template(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        spec: &str,
+        parameters: &[String],
+    ) -> ProvideResult<TemplateEntity>;
+
+    async fn get_all_templates(&mut self) -> ProvideResult<Vec<TemplateEntity>>;
+
+    async fn get_template_by_name(&mut self, name: &str) -> ProvideResult<Option<TemplateEntity>>;
+
+    async fn update_template(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        spec: &str,
+        parameters: &[String],
+    ) -> ProvideResult<Option<TemplateEntity>>;
+
+    async fn delete_template_by_name(
+        &mut self,
+        name: &str,
+    ) -> ProvideResult<Option<TemplateEntity>>;
+
+    async fn create_volume(
+        &mut self,
+        name: &str,
+        driver: &str,
+        environment_id: Option<EntityId>,
+        owner: &str,
+    ) -> ProvideResult<VolumeEntity>;
+
+    /// Record a volume mounted by a new container, unless it is already recorded, in
+    /// which case nothing is returned and the existing record is left untouched.
+    async fn attach_volume(
+        &mut self,
+        name: &str,
+        driver: &str,
+        environment_id: Option<EntityId>,
+        owner: &str,
+    ) -> ProvideResult<Option<VolumeEntity>>;
+
+    async fn get_all_volumes(&mut self) -> ProvideResult<Vec<VolumeEntity>>;
+
+    /// The volumes a user owns, and the volumes of the environments visible to them.
+    async fn get_volumes_visible_to(&mut self, username: &str) -> ProvideResult<Vec<VolumeEntity>>;
+
+    async fn get_volume_by_name(&mut self, name: &str) -> ProvideResult<Option<VolumeEntity>>;
+
+    async fn delete_volume_by_name(&mut self, name: &str) -> ProvideResult<Option<VolumeEntity>>;
+
+    async fn create_registry_credentials(
+        &mut self,
+        registry: &str,
+        username: &str,
+        password: &str,
+        key: &str,
+    ) -> ProvideResult<RegistryEntity>;
+
+    async fn update_registry_credentials(
+        &mut self,
+        registry: &str,
+        username: &str,
+        password: &str,
+        key: &str,
+    ) -> ProvideResult<Option<RegistryEntity>>;
+
+    async fn get_all_registries(&mut self) -> ProvideResult<Vec<RegistryEntity>>;
+
+    async fn get_registry_credentials(
+        &mut self,
+        registry: &str,
+        key: &str,
+    ) -> ProvideResult<Option<RegistryCredentials>>;
+
+    async fn delete_registry_credentials(
+        &mut self,
+        registry: &str,
+    ) -> ProvideResult<Option<RegistryEntity>>;
+
+    async fn get_all_users(&mut self) -> ProvideResult<Vec<UserEntity>>;
+
+    async fn get_user_by_username(&mut self, username: &str) -> ProvideResult<Option<UserEntity>>;
+
+    async fn update_user_roles(
+        &mut self,
+        username: &str,
+        roles: &[String],
+    ) -> ProvideResult<Option<UserEntity>>;
+
+    async fn update_user_teams(
+        &mut self,
+        username: &str,
+        teams: &[String],
+    ) -> ProvideResult<Option<UserEntity>>;
 }
 
 pub type EntityId = Uuid;
@@ -50,6 +353,20 @@ pub struct UserEntity {
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub teams: Vec<String>,
+}
+
+/// A refresh token, used to get a new access token without login again.
+/// The token itself is not kept, only its hash.
+#[derive(Debug, Clone)]
+pub struct RefreshTokenEntity {
+    pub id: EntityId,
+    pub user_id: EntityId,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// The token issued when this one was rotated
+    pub replaced_by: Option<EntityId>,
+    pub created_at: DateTime<Utc>,
 }
 
 // From sqlx realworld example
@@ -67,6 +384,37 @@ pub trait ProvideAuthn {
     async fn get_user_by_email(&mut self, email: &str) -> ProvideResult<Option<UserEntity>>;
 
     async fn update_user(&mut self, updated: &UserEntity) -> ProvideResult<UserEntity>;
+
+    async fn create_refresh_token(
+        &mut self,
+        user_id: EntityId,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ProvideResult<RefreshTokenEntity>;
+
+    async fn get_refresh_token(&mut self, token: &str)
+        -> ProvideResult<Option<RefreshTokenEntity>>;
+
+    /// Revoke a refresh token, unless it was already revoked, in which case
+    /// nothing is returned.
+    async fn revoke_refresh_token(
+        &mut self,
+        id: EntityId,
+        replaced_by: Option<EntityId>,
+    ) -> ProvideResult<Option<RefreshTokenEntity>>;
+
+    async fn revoke_user_refresh_tokens(
+        &mut self,
+        user_id: EntityId,
+    ) -> ProvideResult<Vec<RefreshTokenEntity>>;
+
+    /// Add an access token to the denylist, until it expires.
+    async fn revoke_token(&mut self, jti: &str, expires_at: DateTime<Utc>) -> ProvideResult<()>;
+
+    async fn is_token_revoked(&mut self, jti: &str) -> ProvideResult<bool>;
+
+    /// Forget the revoked access tokens which expired before the given date.
+    async fn delete_expired_revoked_tokens(&mut self, now: DateTime<Utc>) -> ProvideResult<u64>;
 }
 
 pub type ProvideResult<T> = Result<T, ProvideError>;
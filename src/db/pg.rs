@@ -23,6 +23,16 @@ pub struct ContainerEntity {
     pub image: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub env: Vec<String>,
+    pub cmd: Vec<String>,
+    pub entrypoint: Vec<String>,
+    pub ports: Vec<String>,
+    pub mounts: Vec<String>,
+    pub working_dir: Option<String>,
+    pub labels: Vec<String>,
+    pub state: String,
+    pub environment_id: Option<model::EntityId>,
+    pub owner: Option<String>,
 }
 
 impl<'c> FromRow<'c, PgRow<'c>> for ContainerEntity {
@@ -33,6 +43,16 @@ impl<'c> FromRow<'c, PgRow<'c>> for ContainerEntity {
             image: row.get(2),
             created_at: row.get(3),
             updated_at: row.get(4),
+            env: row.get(5),
+            cmd: row.get(6),
+            entrypoint: row.get(7),
+            ports: row.get(8),
+            mounts: row.get(9),
+            working_dir: row.get(10),
+            labels: row.get(11),
+            state: row.get(12),
+            environment_id: row.get(13),
+            owner: row.get(14),
         })
     }
 }
@@ -45,12 +65,34 @@ impl From<ContainerEntity> for model::ContainerEntity {
             image,
             created_at,
             updated_at,
+            env,
+            cmd,
+            entrypoint,
+            ports,
+            mounts,
+            working_dir,
+            labels,
+            state,
+            environment_id,
+            owner,
         } = pg;
 
         model::ContainerEntity {
             id,
             name,
             image,
+            spec: model::ContainerSpec {
+                env,
+                cmd,
+                entrypoint,
+                ports,
+                mounts,
+                working_dir,
+                labels,
+            },
+            state,
+            environment_id,
+            owner,
             created_at,
             updated_at,
         }
@@ -102,17 +144,29 @@ impl model::ProvideData for PgConnection {
         id: &str,
         name: &str,
         image: &str,
+        spec: &model::ContainerSpec,
+        environment_id: Option<model::EntityId>,
+        owner: Option<&str>,
     ) -> model::ProvideResult<model::ContainerEntity> {
         let container: ContainerEntity = sqlx::query_as(
             r#"
-INSERT INTO main.containers ( id, name, image )
-VALUES ( $1, $2, $3 )
+INSERT INTO main.containers ( id, name, image, env, cmd, entrypoint, ports, mounts, working_dir, labels, environment_id, owner )
+VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12 )
 RETURNING *
         "#,
         )
         .bind(id)
         .bind(name)
         .bind(image)
+        .bind(&spec.env)
+        .bind(&spec.cmd)
+        .bind(&spec.entrypoint)
+        .bind(&spec.ports)
+        .bind(&spec.mounts)
+        .bind(&spec.working_dir)
+        .bind(&spec.labels)
+        .bind(environment_id)
+        .bind(owner)
         .fetch_one(self)
         .await?;
 
@@ -138,6 +192,39 @@ ORDER BY created_at
         Ok(containers)
     }
 
+    async fn get_containers_visible_to(
+        &mut self,
+        username: &str,
+    ) -> model::ProvideResult<Vec<model::ContainerEntity>> {
+        let containers: Vec<ContainerEntity> = sqlx::query_as(
+            r#"
+SELECT c.*
+FROM main.containers c
+LEFT JOIN main.environments e ON e.id = c.environment_id
+WHERE c.owner = $1
+   OR e.owner = $1
+   OR EXISTS (
+     SELECT 1
+     FROM main.environment_shares s
+     WHERE s.environment_id = c.environment_id
+       AND ((s.kind = 'user' AND s.grantee = $1)
+         OR (s.kind = 'team' AND s.grantee IN (SELECT UNNEST(teams) FROM main.users WHERE username = $1)))
+   )
+ORDER BY c.created_at
+            "#,
+        )
+        .bind(username)
+        .fetch_all(self)
+        .await?;
+
+        let containers = containers
+            .into_iter()
+            .map(model::ContainerEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(containers)
+    }
+
     async fn get_container_by_name(
         &mut self,
         name: &str,
@@ -171,6 +258,7 @@ WHERE name = $1
 DELETE
 FROM main.containers
 WHERE name = $1
+RETURNING *
             "#,
         )
         .bind(name)
@@ -185,153 +273,1447 @@ WHERE name = $1
             }
         }
     }
-}
 
-/// A user registered with the application (Postgres version)
-pub struct UserEntity {
-    pub id: model::EntityId,
-    pub username: String,
-    pub email: String,
-    pub password: String,
-    pub roles: Vec<String>,
-    pub active: bool,
-    pub created_at: DateTime<Utc>,
-    pub updated_at: DateTime<Utc>,
-}
+    async fn update_container_state(
+        &mut self,
+        name: &str,
+        state: &str,
+    ) -> model::ProvideResult<Option<model::ContainerEntity>> {
+        let container: Option<ContainerEntity> = sqlx::query_as(
+            r#"
+UPDATE main.containers
+SET state = $2, updated_at = DEFAULT
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .bind(state)
+        .fetch_optional(self)
+        .await?;
 
-impl<'c> FromRow<'c, PgRow<'c>> for UserEntity {
-    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
-        Ok(UserEntity {
-            id: row.get(0),
-            username: row.get(1),
-            email: row.get(2),
-            password: row.get(3),
-            roles: row.get(4),
-            active: row.get(5),
-            created_at: row.get(6),
-            updated_at: row.get(7),
-        })
+        Ok(container.map(model::ContainerEntity::from))
     }
-}
 
-impl From<UserEntity> for model::UserEntity {
-    fn from(pg: UserEntity) -> Self {
-        let UserEntity {
-            id,
-            username,
-            email,
-            password,
-            roles,
-            active,
-            created_at,
-            updated_at,
-        } = pg;
+    async fn get_containers_by_environment(
+        &mut self,
+        environment_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::ContainerEntity>> {
+        let containers: Vec<ContainerEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.containers
+WHERE environment_id = $1
+ORDER BY created_at
+            "#,
+        )
+        .bind(environment_id)
+        .fetch_all(self)
+        .await?;
 
-        model::UserEntity {
-            id,
-            username,
-            email,
-            password,
-            roles,
-            active,
-            created_at,
-            updated_at,
-        }
+        let containers = containers
+            .into_iter()
+            .map(model::ContainerEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(containers)
     }
-}
 
-#[async_trait]
-impl model::ProvideAuthn for PgConnection {
-    async fn create_user(
+    async fn create_environment(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        owner: &str,
+        network: Option<&str>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> model::ProvideResult<model::EnvironmentEntity> {
+        let environment: EnvironmentEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.environments ( name, description, owner, network, expires_at )
+VALUES ( $1, $2, $3, $4, $5 )
+RETURNING *
+        "#,
+        )
+        .bind(name)
+        .bind(description)
+        .bind(owner)
+        .bind(network)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(environment.into())
+    }
+
+    async fn get_all_environments(
+        &mut self,
+    ) -> model::ProvideResult<Vec<model::EnvironmentEntity>> {
+        let environments: Vec<EnvironmentEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.environments
+ORDER BY created_at
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let environments = environments
+            .into_iter()
+            .map(model::EnvironmentEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(environments)
+    }
+
+    async fn get_environments_visible_to(
         &mut self,
         username: &str,
-        email: &str,
-        password: &str,
-    ) -> model::ProvideResult<model::UserEntity> {
-        let user: UserEntity = sqlx::query_as(
+    ) -> model::ProvideResult<Vec<model::EnvironmentEntity>> {
+        let environments: Vec<EnvironmentEntity> = sqlx::query_as(
             r#"
-INSERT INTO main.users ( username, email, password )
+SELECT e.*
+FROM main.environments e
+WHERE e.owner = $1
+   OR EXISTS (
+     SELECT 1
+     FROM main.environment_shares s
+     WHERE s.environment_id = e.id
+       AND ((s.kind = 'user' AND s.grantee = $1)
+         OR (s.kind = 'team' AND s.grantee IN (SELECT UNNEST(teams) FROM main.users WHERE username = $1)))
+   )
+ORDER BY e.created_at
+            "#,
+        )
+        .bind(username)
+        .fetch_all(self)
+        .await?;
+
+        let environments = environments
+            .into_iter()
+            .map(model::EnvironmentEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(environments)
+    }
+
+    async fn create_environment_share(
+        &mut self,
+        environment_id: model::EntityId,
+        kind: &str,
+        grantee: &str,
+    ) -> model::ProvideResult<model::ShareEntity> {
+        let share: ShareEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.environment_shares ( environment_id, kind, grantee )
 VALUES ( $1, $2, $3 )
 RETURNING *
         "#,
         )
-        .bind(username)
-        .bind(email)
-        .bind(password)
+        .bind(environment_id)
+        .bind(kind)
+        .bind(grantee)
         .fetch_one(self)
         .await?;
 
-        Ok(user.into())
+        Ok(share.into())
     }
 
-    async fn get_user_by_id(
+    async fn delete_environment_share(
         &mut self,
-        user_id: model::EntityId,
-    ) -> model::ProvideResult<Option<model::UserEntity>> {
-        let user: Option<UserEntity> = sqlx::query_as(
+        environment_id: model::EntityId,
+        kind: &str,
+        grantee: &str,
+    ) -> model::ProvideResult<Option<model::ShareEntity>> {
+        let share: Option<ShareEntity> = sqlx::query_as(
             r#"
-SELECT *
-FROM main.users
-WHERE id = $1
+DELETE
+FROM main.environment_shares
+WHERE environment_id = $1 AND kind = $2 AND grantee = $3
+RETURNING *
             "#,
         )
-        .bind(user_id)
+        .bind(environment_id)
+        .bind(kind)
+        .bind(grantee)
         .fetch_optional(self)
         .await?;
 
-        match user {
-            None => Ok(None),
-            Some(user) => {
-                let user = model::UserEntity::from(user);
-                Ok(Some(user))
-            }
-        }
+        Ok(share.map(model::ShareEntity::from))
     }
 
-    async fn get_user_by_email(
+    async fn get_environment_shares(
         &mut self,
-        email: &str,
-    ) -> model::ProvideResult<Option<model::UserEntity>> {
-        let user: Option<UserEntity> = sqlx::query_as(
+        environment_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::ShareEntity>> {
+        let shares: Vec<ShareEntity> = sqlx::query_as(
             r#"
 SELECT *
-FROM main.users
-WHERE email = $1
+FROM main.environment_shares
+WHERE environment_id = $1
+ORDER BY kind, grantee
             "#,
         )
-        .bind(email)
+        .bind(environment_id)
+        .fetch_all(self)
+        .await?;
+
+        let shares = shares
+            .into_iter()
+            .map(model::ShareEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(shares)
+    }
+
+    async fn get_environment_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.environments
+WHERE name = $1
+            "#,
+        )
+        .bind(name)
         .fetch_optional(self)
         .await?;
 
-        match user {
-            None => Ok(None),
-            Some(user) => {
-                let user = model::UserEntity::from(user);
-                Ok(Some(user))
-            }
-        }
+        Ok(environment.map(model::EnvironmentEntity::from))
     }
 
-    async fn update_user(
+    async fn delete_environment_by_name(
         &mut self,
-        updated: &model::UserEntity,
-    ) -> model::ProvideResult<model::UserEntity> {
-        let user: UserEntity = sqlx::query_as(
+        name: &str,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
             r#"
-UPDATE main.users
-SET email = $1, username = $2, password = $3, updated_at = DEFAULT
-WHERE id = $4
+DELETE
+FROM main.environments
+WHERE name = $1
 RETURNING *
             "#,
         )
-        .bind(updated.email.clone())
-        .bind(updated.username.clone())
-        .bind(updated.password.clone())
-        .bind(updated.id)
-        .fetch_one(self)
+        .bind(name)
+        .fetch_optional(self)
         .await?;
 
-        Ok(user.into())
+        Ok(environment.map(model::EnvironmentEntity::from))
+    }
+
+    async fn update_environment_expiry(
+        &mut self,
+        name: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
+            r#"
+UPDATE main.environments
+SET expires_at = $2, updated_at = DEFAULT
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .bind(expires_at)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(environment.map(model::EnvironmentEntity::from))
+    }
+
+    async fn get_expired_environments(
+        &mut self,
+        now: DateTime<Utc>,
+    ) -> model::ProvideResult<Vec<model::EnvironmentEntity>> {
+        let environments: Vec<EnvironmentEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.environments
+WHERE expires_at <= $1
+ORDER BY expires_at
+            "#,
+        )
+        .bind(now)
+        .fetch_all(self)
+        .await?;
+
+        let environments = environments
+            .into_iter()
+            .map(model::EnvironmentEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(environments)
+    }
+
+    async fn touch_environment(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
+            r#"
+UPDATE main.environments
+SET active_at = NOW()
+WHERE id = $1
+RETURNING *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(environment.map(model::EnvironmentEntity::from))
+    }
+
+    async fn update_environment_stopped(
+        &mut self,
+        name: &str,
+        stopped_at: Option<DateTime<Utc>>,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
+            r#"
+UPDATE main.environments
+SET stopped_at = $2, updated_at = DEFAULT
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .bind(stopped_at)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(environment.map(model::EnvironmentEntity::from))
+    }
+
+    async fn resume_environment(
+        &mut self,
+        id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::EnvironmentEntity>> {
+        let environment: Option<EnvironmentEntity> = sqlx::query_as(
+            r#"
+UPDATE main.environments
+SET stopped_at = NULL, updated_at = DEFAULT
+WHERE id = $1 AND stopped_at IS NOT NULL
+RETURNING *
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(environment.map(model::EnvironmentEntity::from))
+    }
+
+    async fn create_expiration(
+        &mut self,
+        environment: &str,
+        owner: &str,
+        containers: &[String],
+        expired_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::ExpirationEntity> {
+        let expiration: ExpirationEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.expirations ( environment, owner, containers, expired_at )
+VALUES ( $1, $2, $3, $4 )
+RETURNING *
+        "#,
+        )
+        .bind(environment)
+        .bind(owner)
+        .bind(containers)
+        .bind(expired_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(expiration.into())
+    }
+
+    async fn get_all_expirations(&mut self) -> model::ProvideResult<Vec<model::ExpirationEntity>> {
+        let expirations: Vec<ExpirationEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.expirations
+ORDER BY removed_at DESC
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let expirations = expirations
+            .into_iter()
+            .map(model::ExpirationEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(expirations)
+    }
+
+    async fn get_expirations_by_owner(
+        &mut self,
+        owner: &str,
+    ) -> model::ProvideResult<Vec<model::ExpirationEntity>> {
+        let expirations: Vec<ExpirationEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.expirations
+WHERE owner = $1
+ORDER BY removed_at DESC
+            "#,
+        )
+        .bind(owner)
+        .fetch_all(self)
+        .await?;
+
+        let expirations = expirations
+            .into_iter()
+            .map(model::ExpirationEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(expirations)
+    }
+
+    async fn create_template(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        spec: &str,
+        parameters: &[String],
+    ) -> model::ProvideResult<model::TemplateEntity> {
+        let template: TemplateEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.templates ( name, description, spec, parameters )
+VALUES ( $1, $2, $3, $4 )
+RETURNING *
+        "#,
+        )
+        .bind(name)
+        .bind(description)
+        .bind(spec)
+        .bind(parameters)
+        .fetch_one(self)
+        .await?;
+
+        Ok(template.into())
+    }
+
+    async fn get_all_templates(&mut self) -> model::ProvideResult<Vec<model::TemplateEntity>> {
+        let templates: Vec<TemplateEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.templates
+ORDER BY name
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let templates = templates
+            .into_iter()
+            .map(model::TemplateEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(templates)
+    }
+
+    async fn get_template_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::TemplateEntity>> {
+        let template: Option<TemplateEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.templates
+WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(template.map(model::TemplateEntity::from))
+    }
+
+    async fn update_template(
+        &mut self,
+        name: &str,
+        description: Option<&str>,
+        spec: &str,
+        parameters: &[String],
+    ) -> model::ProvideResult<Option<model::TemplateEntity>> {
+        let template: Option<TemplateEntity> = sqlx::query_as(
+            r#"
+UPDATE main.templates
+SET description = $2, spec = $3, parameters = $4, updated_at = DEFAULT
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .bind(description)
+        .bind(spec)
+        .bind(parameters)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(template.map(model::TemplateEntity::from))
+    }
+
+    async fn delete_template_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::TemplateEntity>> {
+        let template: Option<TemplateEntity> = sqlx::query_as(
+            r#"
+DELETE
+FROM main.templates
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(template.map(model::TemplateEntity::from))
+    }
+
+    async fn create_volume(
+        &mut self,
+        name: &str,
+        driver: &str,
+        environment_id: Option<model::EntityId>,
+        owner: &str,
+    ) -> model::ProvideResult<model::VolumeEntity> {
+        let volume: VolumeEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.volumes ( name, driver, environment_id, owner )
+VALUES ( $1, $2, $3, $4 )
+RETURNING *
+        "#,
+        )
+        .bind(name)
+        .bind(driver)
+        .bind(environment_id)
+        .bind(owner)
+        .fetch_one(self)
+        .await?;
+
+        Ok(volume.into())
+    }
+
+    async fn attach_volume(
+        &mut self,
+        name: &str,
+        driver: &str,
+        environment_id: Option<model::EntityId>,
+        owner: &str,
+    ) -> model::ProvideResult<Option<model::VolumeEntity>> {
+        let volume: Option<VolumeEntity> = sqlx::query_as(
+            r#"
+INSERT INTO main.volumes ( name, driver, environment_id, owner )
+VALUES ( $1, $2, $3, $4 )
+ON CONFLICT ( name ) DO NOTHING
+RETURNING *
+        "#,
+        )
+        .bind(name)
+        .bind(driver)
+        .bind(environment_id)
+        .bind(owner)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(volume.map(model::VolumeEntity::from))
+    }
+
+    async fn get_all_volumes(&mut self) -> model::ProvideResult<Vec<model::VolumeEntity>> {
+        let volumes: Vec<VolumeEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.volumes
+ORDER BY name
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let volumes = volumes
+            .into_iter()
+            .map(model::VolumeEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(volumes)
+    }
+
+    async fn get_volumes_visible_to(
+        &mut self,
+        username: &str,
+    ) -> model::ProvideResult<Vec<model::VolumeEntity>> {
+        let volumes: Vec<VolumeEntity> = sqlx::query_as(
+            r#"
+SELECT v.*
+FROM main.volumes v
+LEFT JOIN main.environments e ON e.id = v.environment_id
+WHERE v.owner = $1
+   OR e.owner = $1
+   OR EXISTS (
+     SELECT 1
+     FROM main.environment_shares s
+     WHERE s.environment_id = v.environment_id
+       AND ((s.kind = 'user' AND s.grantee = $1)
+         OR (s.kind = 'team' AND s.grantee IN (SELECT UNNEST(teams) FROM main.users WHERE username = $1)))
+   )
+ORDER BY v.name
+            "#,
+        )
+        .bind(username)
+        .fetch_all(self)
+        .await?;
+
+        let volumes = volumes
+            .into_iter()
+            .map(model::VolumeEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(volumes)
+    }
+
+    async fn get_volume_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::VolumeEntity>> {
+        let volume: Option<VolumeEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.volumes
+WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(volume.map(model::VolumeEntity::from))
+    }
+
+    async fn delete_volume_by_name(
+        &mut self,
+        name: &str,
+    ) -> model::ProvideResult<Option<model::VolumeEntity>> {
+        let volume: Option<VolumeEntity> = sqlx::query_as(
+            r#"
+DELETE
+FROM main.volumes
+WHERE name = $1
+RETURNING *
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(volume.map(model::VolumeEntity::from))
+    }
+
+    async fn create_registry_credentials(
+        &mut self,
+        registry: &str,
+        username: &str,
+        password: &str,
+        key: &str,
+    ) -> model::ProvideResult<model::RegistryEntity> {
+        let entity: RegistryEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.registry_credentials ( registry, username, password )
+VALUES ( $1, $2, main.pgp_sym_encrypt($3, $4) )
+RETURNING registry, username, created_at, updated_at
+        "#,
+        )
+        .bind(registry)
+        .bind(username)
+        .bind(password)
+        .bind(key)
+        .fetch_one(self)
+        .await?;
+
+        Ok(entity.into())
+    }
+
+    async fn update_registry_credentials(
+        &mut self,
+        registry: &str,
+        username: &str,
+        password: &str,
+        key: &str,
+    ) -> model::ProvideResult<Option<model::RegistryEntity>> {
+        let entity: Option<RegistryEntity> = sqlx::query_as(
+            r#"
+UPDATE main.registry_credentials
+SET username = $2, password = main.pgp_sym_encrypt($3, $4), updated_at = DEFAULT
+WHERE registry = $1
+RETURNING registry, username, created_at, updated_at
+            "#,
+        )
+        .bind(registry)
+        .bind(username)
+        .bind(password)
+        .bind(key)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(entity.map(model::RegistryEntity::from))
+    }
+
+    async fn get_all_registries(&mut self) -> model::ProvideResult<Vec<model::RegistryEntity>> {
+        let entities: Vec<RegistryEntity> = sqlx::query_as(
+            r#"
+SELECT registry, username, created_at, updated_at
+FROM main.registry_credentials
+ORDER BY registry
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let entities = entities
+            .into_iter()
+            .map(model::RegistryEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(entities)
+    }
+
+    async fn get_registry_credentials(
+        &mut self,
+        registry: &str,
+        key: &str,
+    ) -> model::ProvideResult<Option<model::RegistryCredentials>> {
+        let credentials: Option<(String, String)> = sqlx::query_as(
+            r#"
+SELECT username, main.pgp_sym_decrypt(password, $2)
+FROM main.registry_credentials
+WHERE registry = $1
+            "#,
+        )
+        .bind(registry)
+        .bind(key)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(credentials
+            .map(|(username, password)| model::RegistryCredentials { username, password }))
+    }
+
+    async fn delete_registry_credentials(
+        &mut self,
+        registry: &str,
+    ) -> model::ProvideResult<Option<model::RegistryEntity>> {
+        let entity: Option<RegistryEntity> = sqlx::query_as(
+            r#"
+DELETE
+FROM main.registry_credentials
+WHERE registry = $1
+RETURNING registry, username, created_at, updated_at
+            "#,
+        )
+        .bind(registry)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(entity.map(model::RegistryEntity::from))
+    }
+
+    async fn get_all_users(&mut self) -> model::ProvideResult<Vec<model::UserEntity>> {
+        let users: Vec<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+ORDER BY username
+            "#,
+        )
+        .fetch_all(self)
+        .await?;
+
+        let users = users
+            .into_iter()
+            .map(model::UserEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(users)
+    }
+
+    async fn get_user_by_username(
+        &mut self,
+        username: &str,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE username = $1
+            "#,
+        )
+        .bind(username)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(user.map(model::UserEntity::from))
+    }
+
+    async fn update_user_roles(
+        &mut self,
+        username: &str,
+        roles: &[String],
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET roles = $2, updated_at = DEFAULT
+WHERE username = $1
+RETURNING *
+            "#,
+        )
+        .bind(username)
+        .bind(roles)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(user.map(model::UserEntity::from))
+    }
+
+    async fn update_user_teams(
+        &mut self,
+        username: &str,
+        teams: &[String],
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET teams = $2, updated_at = DEFAULT
+WHERE username = $1
+RETURNING *
+            "#,
+        )
+        .bind(username)
+        .bind(teams)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(user.map(model::UserEntity::from))
+    }
+}
+
+/// An environment (Postgres version)
+pub struct EnvironmentEntity {
+    pub id: model::EntityId,
+    pub name: String,
+    pub description: Option<String>,
+    pub owner: String,
+    pub network: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub active_at: DateTime<Utc>,
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for EnvironmentEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(EnvironmentEntity {
+            id: row.get(0),
+            name: row.get(1),
+            description: row.get(2),
+            owner: row.get(3),
+            network: row.get(4),
+            created_at: row.get(5),
+            updated_at: row.get(6),
+            expires_at: row.get(7),
+            active_at: row.get(8),
+            stopped_at: row.get(9),
+        })
+    }
+}
+
+impl From<EnvironmentEntity> for model::EnvironmentEntity {
+    fn from(pg: EnvironmentEntity) -> Self {
+        let EnvironmentEntity {
+            id,
+            name,
+            description,
+            owner,
+            network,
+            created_at,
+            updated_at,
+            expires_at,
+            active_at,
+            stopped_at,
+        } = pg;
+
+        model::EnvironmentEntity {
+            id,
+            name,
+            description,
+            owner,
+            network,
+            expires_at,
+            active_at,
+            stopped_at,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// An environment torn down by the expiry scheduler (Postgres version)
+pub struct ExpirationEntity {
+    pub id: model::EntityId,
+    pub environment: String,
+    pub owner: String,
+    pub containers: Vec<String>,
+    pub expired_at: DateTime<Utc>,
+    pub removed_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for ExpirationEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(ExpirationEntity {
+            id: row.get(0),
+            environment: row.get(1),
+            owner: row.get(2),
+            containers: row.get(3),
+            expired_at: row.get(4),
+            removed_at: row.get(5),
+        })
+    }
+}
+
+impl From<ExpirationEntity> for model::ExpirationEntity {
+    fn from(pg: ExpirationEntity) -> Self {
+        let ExpirationEntity {
+            id,
+            environment,
+            owner,
+            containers,
+            expired_at,
+            removed_at,
+        } = pg;
+
+        model::ExpirationEntity {
+            id,
+            environment,
+            owner,
+            containers,
+            expired_at,
+            removed_at,
+        }
+    }
+}
+
+/// An environment share (Postgres version)
+pub struct ShareEntity {
+    pub environment_id: model::EntityId,
+    pub kind: String,
+    pub grantee: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for ShareEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(ShareEntity {
+            environment_id: row.get(0),
+            kind: row.get(1),
+            grantee: row.get(2),
+            created_at: row.get(3),
+        })
+    }
+}
+
+impl From<ShareEntity> for model::ShareEntity {
+    fn from(pg: ShareEntity) -> Self {
+        let ShareEntity {
+            environment_id,
+            kind,
+            grantee,
+            created_at,
+        } = pg;
+
+        model::ShareEntity {
+            environment_id,
+            kind,
+            grantee,
+            created_at,
+        }
+    }
+}
+
+/// An environment template (Postgres version)
+pub struct TemplateEntity {
+    pub id: model::EntityId,
+    pub name: String,
+    pub description: Option<String>,
+    pub spec: String,
+    pub parameters: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for TemplateEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(TemplateEntity {
+            id: row.get(0),
+            name: row.get(1),
+            description: row.get(2),
+            spec: row.get(3),
+            parameters: row.get(4),
+            created_at: row.get(5),
+            updated_at: row.get(6),
+        })
+    }
+}
+
+impl From<TemplateEntity> for model::TemplateEntity {
+    fn from(pg: TemplateEntity) -> Self {
+        let TemplateEntity {
+            id,
+            name,
+            description,
+            spec,
+            parameters,
+            created_at,
+            updated_at,
+        } = pg;
+
+        model::TemplateEntity {
+            id,
+            name,
+            description,
+            spec,
+            parameters,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// A named volume (Postgres version)
+pub struct VolumeEntity {
+    pub name: String,
+    pub driver: String,
+    pub environment_id: Option<model::EntityId>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub owner: Option<String>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for VolumeEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(VolumeEntity {
+            name: row.get(0),
+            driver: row.get(1),
+            environment_id: row.get(2),
+            created_at: row.get(3),
+            updated_at: row.get(4),
+            owner: row.get(5),
+        })
+    }
+}
+
+impl From<VolumeEntity> for model::VolumeEntity {
+    fn from(pg: VolumeEntity) -> Self {
+        let VolumeEntity {
+            name,
+            driver,
+            environment_id,
+            created_at,
+            updated_at,
+            owner,
+        } = pg;
+
+        model::VolumeEntity {
+            name,
+            driver,
+            environment_id,
+            owner,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// Registry credentials, without the password (Postgres version)
+pub struct RegistryEntity {
+    pub registry: String,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for RegistryEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(RegistryEntity {
+            registry: row.get(0),
+            username: row.get(1),
+            created_at: row.get(2),
+            updated_at: row.get(3),
+        })
+    }
+}
+
+impl From<RegistryEntity> for model::RegistryEntity {
+    fn from(pg: RegistryEntity) -> Self {
+        let RegistryEntity {
+            registry,
+            username,
+            created_at,
+            updated_at,
+        } = pg;
+
+        model::RegistryEntity {
+            registry,
+            username,
+            created_at,
+            updated_at,
+        }
+    }
+}
+
+/// A user registered with the application (Postgres version)
+pub struct UserEntity {
+    pub id: model::EntityId,
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    pub roles: Vec<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub teams: Vec<String>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for UserEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(UserEntity {
+            id: row.get(0),
+            username: row.get(1),
+            email: row.get(2),
+            password: row.get(3),
+            roles: row.get(4),
+            active: row.get(5),
+            created_at: row.get(6),
+            updated_at: row.get(7),
+            teams: row.get(8),
+        })
+    }
+}
+
+impl From<UserEntity> for model::UserEntity {
+    fn from(pg: UserEntity) -> Self {
+        let UserEntity {
+            id,
+            username,
+            email,
+            password,
+            roles,
+            active,
+            created_at,
+            updated_at,
+            teams,
+        } = pg;
+
+        model::UserEntity {
+            id,
+            username,
+            email,
+            password,
+            roles,
+            active,
+            created_at,
+            updated_at,
+            teams,
+        }
+    }
+}
+
+/// A refresh token (Postgres version)
+/// The token hash is never selected.
+pub struct RefreshTokenEntity {
+    pub id: model::EntityId,
+    pub user_id: model::EntityId,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub replaced_by: Option<model::EntityId>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'c> FromRow<'c, PgRow<'c>> for RefreshTokenEntity {
+    fn from_row(row: &PgRow<'c>) -> Result<Self, sqlx::Error> {
+        Ok(RefreshTokenEntity {
+            id: row.get(0),
+            user_id: row.get(1),
+            expires_at: row.get(2),
+            revoked_at: row.get(3),
+            replaced_by: row.get(4),
+            created_at: row.get(5),
+        })
+    }
+}
+
+impl From<RefreshTokenEntity> for model::RefreshTokenEntity {
+    fn from(pg: RefreshTokenEntity) -> Self {
+        let RefreshTokenEntity {
+            id,
+            user_id,
+            expires_at,
+            revoked_at,
+            replaced_by,
+            created_at,
+        } = pg;
+
+        model::RefreshTokenEntity {
+            id,
+            user_id,
+            expires_at,
+            revoked_at,
+            replaced_by,
+            created_at,
+        }
+    }
+}
+
+#[async_trait]
+impl model::ProvideAuthn for PgConnection {
+    async fn create_user(
+        &mut self,
+        username: &str,
+        email: &str,
+        password: &str,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.users ( username, email, password )
+VALUES ( $1, $2, $3 )
+RETURNING *
+        "#,
+        )
+        .bind(username)
+        .bind(email)
+        .bind(password)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn get_user_by_id(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(self)
+        .await?;
+
+        match user {
+            None => Ok(None),
+            Some(user) => {
+                let user = model::UserEntity::from(user);
+                Ok(Some(user))
+            }
+        }
+    }
+
+    async fn get_user_by_email(
+        &mut self,
+        email: &str,
+    ) -> model::ProvideResult<Option<model::UserEntity>> {
+        let user: Option<UserEntity> = sqlx::query_as(
+            r#"
+SELECT *
+FROM main.users
+WHERE email = $1
+            "#,
+        )
+        .bind(email)
+        .fetch_optional(self)
+        .await?;
+
+        match user {
+            None => Ok(None),
+            Some(user) => {
+                let user = model::UserEntity::from(user);
+                Ok(Some(user))
+            }
+        }
+    }
+
+    async fn update_user(
+        &mut self,
+        updated: &model::UserEntity,
+    ) -> model::ProvideResult<model::UserEntity> {
+        let user: UserEntity = sqlx::query_as(
+            r#"
+UPDATE main.users
+SET email = $1, username = $2, password = $3, updated_at = DEFAULT
+WHERE id = $4
+RETURNING *
+            "#,
+        )
+        .bind(updated.email.clone())
+        .bind(updated.username.clone())
+        .bind(updated.password.clone())
+        .bind(updated.id)
+        .fetch_one(self)
+        .await?;
+
+        Ok(user.into())
+    }
+
+    async fn create_refresh_token(
+        &mut self,
+        user_id: model::EntityId,
+        token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<model::RefreshTokenEntity> {
+        let token: RefreshTokenEntity = sqlx::query_as(
+            r#"
+INSERT INTO main.refresh_tokens ( user_id, token_hash, expires_at )
+VALUES ( $1, main.digest($2, 'sha256'), $3 )
+RETURNING id, user_id, expires_at, revoked_at, replaced_by, created_at
+        "#,
+        )
+        .bind(user_id)
+        .bind(token)
+        .bind(expires_at)
+        .fetch_one(self)
+        .await?;
+
+        Ok(token.into())
+    }
+
+    async fn get_refresh_token(
+        &mut self,
+        token: &str,
+    ) -> model::ProvideResult<Option<model::RefreshTokenEntity>> {
+        let token: Option<RefreshTokenEntity> = sqlx::query_as(
+            r#"
+SELECT id, user_id, expires_at, revoked_at, replaced_by, created_at
+FROM main.refresh_tokens
+WHERE token_hash = main.digest($1, 'sha256')
+            "#,
+        )
+        .bind(token)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(token.map(model::RefreshTokenEntity::from))
+    }
+
+    async fn revoke_refresh_token(
+        &mut self,
+        id: model::EntityId,
+        replaced_by: Option<model::EntityId>,
+    ) -> model::ProvideResult<Option<model::RefreshTokenEntity>> {
+        let token: Option<RefreshTokenEntity> = sqlx::query_as(
+            r#"
+UPDATE main.refresh_tokens
+SET revoked_at = NOW(), replaced_by = $2
+WHERE id = $1 AND revoked_at IS NULL
+RETURNING id, user_id, expires_at, revoked_at, replaced_by, created_at
+            "#,
+        )
+        .bind(id)
+        .bind(replaced_by)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(token.map(model::RefreshTokenEntity::from))
+    }
+
+    async fn revoke_user_refresh_tokens(
+        &mut self,
+        user_id: model::EntityId,
+    ) -> model::ProvideResult<Vec<model::RefreshTokenEntity>> {
+        let tokens: Vec<RefreshTokenEntity> = sqlx::query_as(
+            r#"
+UPDATE main.refresh_tokens
+SET revoked_at = NOW()
+WHERE user_id = $1 AND revoked_at IS NULL
+RETURNING id, user_id, expires_at, revoked_at, replaced_by, created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(self)
+        .await?;
+
+        let tokens = tokens
+            .into_iter()
+            .map(model::RefreshTokenEntity::from)
+            .collect::<Vec<_>>();
+
+        Ok(tokens)
+    }
+
+    async fn revoke_token(
+        &mut self,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+    ) -> model::ProvideResult<()> {
+        // Revoking a token twice is not an error.
+        let _: Option<(String,)> = sqlx::query_as(
+            r#"
+INSERT INTO main.revoked_tokens ( jti, expires_at )
+VALUES ( $1, $2 )
+ON CONFLICT (jti) DO NOTHING
+RETURNING jti
+            "#,
+        )
+        .bind(jti)
+        .bind(expires_at)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn is_token_revoked(&mut self, jti: &str) -> model::ProvideResult<bool> {
+        let revoked: Option<(String,)> = sqlx::query_as(
+            r#"
+SELECT jti
+FROM main.revoked_tokens
+WHERE jti = $1
+            "#,
+        )
+        .bind(jti)
+        .fetch_optional(self)
+        .await?;
+
+        Ok(revoked.is_some())
+    }
+
+    async fn delete_expired_revoked_tokens(
+        &mut self,
+        now: DateTime<Utc>,
+    ) -> model::ProvideResult<u64> {
+        let deleted: Vec<(String,)> = sqlx::query_as(
+            r#"
+DELETE FROM main.revoked_tokens
+WHERE expires_at < $1
+RETURNING jti
+            "#,
+        )
+        .bind(now)
+        .fetch_all(self)
+        .await?;
+
+        Ok(deleted.len() as u64)
     }
 }
 
@@ -71,6 +71,50 @@ pub enum Error {
         // source: argonautica::Error, Does not implement Error
     },
 
+    #[snafu(display(
+        "Requested {} ({}) exceeds the maximum allowed ({})",
+        resource,
+        requested,
+        maximum
+    ))]
+    #[snafu(visibility(pub))]
+    LimitExceeded {
+        resource: String,
+        requested: i64,
+        maximum: i64,
+    },
+
+    #[snafu(display("Invalid {}: {}", field, msg))]
+    #[snafu(visibility(pub))]
+    ValidationError { field: String, msg: String },
+
+    #[snafu(display("Hyper Error: {} - {}", msg, source))]
+    #[snafu(visibility(pub))]
+    HyperError { msg: String, source: hyper::Error },
+
+    #[snafu(display("YAML Error: {} - {}", msg, source))]
+    #[snafu(visibility(pub))]
+    YAMLError {
+        msg: String,
+        source: serde_yaml::Error,
+    },
+
+    #[snafu(display("Spec Error: {}", msg))]
+    #[snafu(visibility(pub))]
+    SpecError { msg: String },
+
+    #[snafu(display("Image {} is not allowed: {}", image, reason))]
+    #[snafu(visibility(pub))]
+    ImagePolicyViolation { image: String, reason: String },
+
+    #[snafu(display("Unauthenticated: {}", msg))]
+    #[snafu(visibility(pub))]
+    Unauthenticated { msg: String },
+
+    #[snafu(display("Forbidden: {}", msg))]
+    #[snafu(visibility(pub))]
+    Forbidden { msg: String },
+
     #[snafu(display("Bollard Error: {} - {}", msg, source))]
     #[snafu(visibility(pub))]
     BollardError {
@@ -163,6 +207,58 @@ impl IntoFieldError for Error {
                 FieldError::new("Hasher Error", graphql_value!({ "internal_error": errmsg }))
             }
 
+            err @ Error::LimitExceeded { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Limit Exceeded",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::ValidationError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Validation Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::HyperError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Hyper Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::YAMLError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("YAML Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::SpecError { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Spec Error", graphql_value!({ "internal_error": errmsg }))
+            }
+
+            err @ Error::ImagePolicyViolation { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Image Policy Violation",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::Unauthenticated { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new(
+                    "Authentication Error",
+                    graphql_value!({ "internal_error": errmsg }),
+                )
+            }
+
+            err @ Error::Forbidden { .. } => {
+                let errmsg = format!("{}", err);
+                FieldError::new("Forbidden", graphql_value!({ "internal_error": errmsg }))
+            }
+
             err @ Error::BollardError { .. } => {
                 let errmsg = format!("{}", err);
                 FieldError::new(
@@ -1,11 +1,12 @@
 use clap::ArgMatches;
-use environments::api::gql;
+use environments::api::{expiry, gql, idle, reconcile, subscriptions, terminal, users};
 use environments::error;
 use environments::settings::Settings;
 use environments::state::State;
 use slog::{info, Logger};
 use snafu::ResultExt;
 use std::net::ToSocketAddrs;
+use std::sync::Arc;
 use warp::{self, http, Filter};
 
 #[allow(clippy::needless_lifetimes)]
@@ -19,6 +20,17 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
     // We keep a copy of the logger before the context takes ownership of it.
     let logger = state.logger.clone();
 
+    users::promote_admins(&settings.users.admins, &state).await?;
+
+    tokio::spawn(reconcile::run_reconciler(
+        state.clone(),
+        settings.reconciler.clone(),
+    ));
+
+    tokio::spawn(expiry::run_expiry(state.clone(), settings.expiry.clone()));
+
+    tokio::spawn(idle::run_idle(state.clone(), settings.idle.clone()));
+
     let state = warp::any().map(move || state.clone());
 
     let cors = warp::cors()
@@ -30,28 +42,85 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
 
     let log = warp::log("foo");
 
-    let auth = warp::header::<String>("authorization")
-        .map(|bearer: String| match bearer.strip_prefix("Bearer ") {
-            Some(token) => Some(String::from(token)),
-            None => None,
-        })
-        .or(warp::any().map(|| None))
-        .unify();
-
-    let context = warp::any()
-        .and(state.clone())
-        .and(auth)
-        .map(move |state, token| gql::Context { state, token });
+    // Requests without authorization header are anonymous, while a malformed header is
+    // rejected.
+    let auth = warp::header::optional::<String>("authorization").and_then(
+        |authorization: Option<String>| async move {
+            authorization
+                .map(|authorization| gql::bearer_token(&authorization))
+                .transpose()
+                .map_err(|err| warp::reject::custom(Unauthenticated(err.to_string())))
+        },
+    );
+
+    // Requests with an invalid, expired, or revoked token are rejected before reaching the
+    // resolvers.
+    let context =
+        warp::any()
+            .and(state.clone())
+            .and(auth.clone())
+            .and_then(|state, token| async move {
+                gql::Context::new(state, token)
+                    .await
+                    .map_err(|err| warp::reject::custom(Unauthenticated(err.to_string())))
+            });
 
     let playground = warp::get()
         .and(warp::path("playground"))
         .and(playground_filter("/graphql", Some("/subscriptions")));
 
-    let graphql_filter = juniper_warp::make_graphql_filter(gql::schema(), context.boxed());
+    let graphql_filter = juniper_warp::make_graphql_filter(gql::schema(), context.clone().boxed());
 
     let graphql = warp::post().and(warp::path("graphql")).and(graphql_filter);
 
-    let routes = playground.or(graphql).with(cors).with(log);
+    let coordinator = Arc::new(gql::Coordinator::new(gql::schema()));
+
+    // Websockets are authenticated once opened, since browsers cannot set their headers,
+    // and their token may expire, or be revoked, while they are open.
+    let subscriptions = warp::path("subscriptions")
+        .and(warp::ws())
+        .and(state.clone())
+        .and(auth.clone())
+        .and(warp::any().map(move || Arc::clone(&coordinator)))
+        .map(
+            |ws: warp::ws::Ws,
+             state: State,
+             token: Option<String>,
+             coordinator: Arc<gql::Coordinator>| {
+                ws.on_upgrade(move |websocket| {
+                    subscriptions::run_session(websocket, coordinator, state, token)
+                })
+            },
+        )
+        .map(|reply| {
+            // The playground expects the graphql-ws protocol to be acknowledged.
+            warp::reply::with_header(reply, "Sec-WebSocket-Protocol", "graphql-ws")
+        });
+
+    let terminal = warp::path!("containers" / String / "terminal")
+        .and(warp::ws())
+        .and(warp::query::<terminal::SessionQuery>())
+        .and(state.clone())
+        .and(auth.clone())
+        .map(
+            |name: String,
+             ws: warp::ws::Ws,
+             query: terminal::SessionQuery,
+             state: State,
+             token: Option<String>| {
+                ws.on_upgrade(move |websocket| {
+                    terminal::run_session(websocket, name, query, state, token)
+                })
+            },
+        );
+
+    let routes = playground
+        .or(graphql)
+        .or(subscriptions)
+        .or(terminal)
+        .recover(handle_rejection)
+        .with(cors)
+        .with(log);
 
     let host = settings.service.host;
     let port = settings.service.port;
@@ -72,6 +141,32 @@ pub async fn run_server(settings: Settings, state: State) -> Result<(), error::E
     Ok(())
 }
 
+/// The rejection of a request whose token is invalid, expired, or revoked.
+#[derive(Debug)]
+struct Unauthenticated(String);
+
+impl warp::reject::Reject for Unauthenticated {}
+
+/// Reply to unauthenticated requests with a 401, and a body shaped like a GraphQL error,
+/// so that clients can handle it like the other errors.
+async fn handle_rejection(rejection: warp::Rejection) -> Result<impl warp::Reply, warp::Rejection> {
+    match rejection.find::<Unauthenticated>() {
+        Some(Unauthenticated(msg)) => {
+            let body = serde_json::json!({
+                "errors": [{
+                    "message": "Authentication Error",
+                    "extensions": { "internal_error": msg }
+                }]
+            });
+            Ok(warp::reply::with_status(
+                warp::reply::json(&body),
+                http::StatusCode::UNAUTHORIZED,
+            ))
+        }
+        None => Err(rejection),
+    }
+}
+
 /// Create a filter that replies with an HTML page containing GraphQL Playground.
 /// This does not handle routing, so you can mount it on any endpoint.
 pub fn playground_filter(
@@ -27,7 +27,96 @@ pub struct Argon {
 #[derive(Debug, Clone, Deserialize)]
 pub struct Jwt {
     pub secret: String,
+    /// Number of minutes an access token is valid
     pub duration: i64,
+    /// Number of minutes a refresh token is valid
+    pub refresh_duration: i64,
+    /// The issuer of the tokens, checked when they are decoded
+    pub issuer: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Registry {
+    /// Key used to encrypt the registry credentials stored in the database
+    pub secret: String,
+}
+
+/// An image whose content is pinned to a digest
+#[derive(Debug, Clone, Deserialize)]
+pub struct Pin {
+    /// A glob pattern matched against the image name and tag, eg 'docker.io/library/postgres:12'
+    pub image: String,
+    /// The digest of the image content, eg 'sha256:...'
+    pub digest: String,
+}
+
+/// Which images containers can be created from.
+/// Patterns are globs matched against fully qualified image names and tags, eg
+/// 'docker.io/library/*'. Denied patterns take precedence, and an empty allow list
+/// allows everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default)]
+    pub pins: Vec<Pin>,
+}
+
+/// Nobody can grant roles on a new deployment, so the users listed in `admins` are
+/// granted the admin role when the service starts, once they registered.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Users {
+    #[serde(default)]
+    pub admins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Reconciler {
+    /// Number of seconds between two reconciliations
+    pub interval: u64,
+    /// Remove orphan containers, and records of missing containers
+    pub garbage_collect: bool,
+    /// Number of seconds an orphan container is left alone after its creation, since it
+    /// may not be recorded yet
+    pub grace_period: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Expiry {
+    /// Number of seconds between two checks for expired environments
+    pub interval: u64,
+}
+
+/// An environment is idle when none of its containers used more than `cpu_percent`, or
+/// sent or received more than `network_bytes`, between two checks, and it was not accessed
+/// through the API. Idle environments are stopped after `timeout` seconds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Idle {
+    /// Number of seconds between two checks for idle environments
+    pub interval: u64,
+    pub timeout: u64,
+    pub cpu_percent: f64,
+    pub network_bytes: f64,
+}
+
+/// Resources allocated to a container. Memory is in megabytes, and the cpu quota
+/// in microseconds per 100ms period.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResourceLimits {
+    pub memory_mb: Option<i64>,
+    pub cpu_shares: Option<i64>,
+    pub cpu_quota: Option<i64>,
+    pub pids_limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Limits {
+    /// Resources given to containers which do not request any
+    pub default: ResourceLimits,
+    /// Maximum resources a container can request
+    pub maximum: ResourceLimits,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -37,8 +126,16 @@ pub struct Settings {
     pub mode: String,
     pub argon: Argon,
     pub jwt: Jwt,
+    pub registry: Registry,
     pub database: Database,
     pub service: Service,
+    pub reconciler: Reconciler,
+    pub expiry: Expiry,
+    pub idle: Idle,
+    pub limits: Limits,
+    pub policy: Policy,
+    #[serde(default)]
+    pub users: Users,
 }
 
 // TODO Parameterize the config directory
@@ -110,8 +207,49 @@ impl Settings {
         }
 
         // You can deserialize (and thus freeze) the entire configuration as
-        s.try_into().context(error::ConfigError {
+        let settings: Settings = s.try_into().context(error::ConfigError {
             msg: String::from("Could not generate settings from configuration"),
-        })
+        })?;
+
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    /// Check the values the configuration cannot express constraints on.
+    fn validate(&self) -> Result<(), error::Error> {
+        check_interval("reconciler.interval", self.reconciler.interval)?;
+        check_interval("expiry.interval", self.expiry.interval)?;
+        check_interval("idle.interval", self.idle.interval)?;
+        Ok(())
+    }
+}
+
+/// The background tasks run on a periodic timer, which cannot have a zero period.
+fn check_interval(field: &str, interval: u64) -> Result<(), error::Error> {
+    if interval == 0 {
+        return Err(error::Error::ValidationError {
+            field: String::from(field),
+            msg: String::from("the interval must be at least one second"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_interval_rejects_zero() {
+        assert!(matches!(
+            check_interval("reconciler.interval", 0),
+            Err(error::Error::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn check_interval_accepts_positive() {
+        assert!(check_interval("reconciler.interval", 1).is_ok());
     }
 }
@@ -0,0 +1,75 @@
+use bollard::Docker;
+use snafu::ResultExt;
+use std::env;
+
+use crate::error;
+
+/// The socket used when DOCKER_HOST is not set.
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Where the docker engine listens, following the same convention as bollard and the
+/// docker client: DOCKER_HOST is either 'unix:///path/to/socket' or 'tcp://host:port'.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DockerHost {
+    Unix(String),
+    Tcp(String),
+}
+
+impl DockerHost {
+    pub fn from_env() -> Self {
+        DockerHost::parse(env::var("DOCKER_HOST").ok().as_deref())
+    }
+
+    fn parse(host: Option<&str>) -> Self {
+        match host {
+            Some(host) if host.starts_with("tcp://") => {
+                DockerHost::Tcp(String::from(host.trim_start_matches("tcp://")))
+            }
+            Some(host) if host.starts_with("unix://") => {
+                DockerHost::Unix(String::from(host.trim_start_matches("unix://")))
+            }
+            _ => DockerHost::Unix(String::from(DEFAULT_SOCKET)),
+        }
+    }
+
+    /// Connect bollard to the docker engine. Both bollard constructors read DOCKER_HOST
+    /// themselves.
+    pub fn connect(&self) -> Result<Docker, error::Error> {
+        let docker = match self {
+            DockerHost::Unix(_) => Docker::connect_with_unix_defaults(),
+            DockerHost::Tcp(_) => Docker::connect_with_http_defaults(),
+        };
+        docker.context(error::BollardError {
+            msg: String::from("Could not establish connection with docker engine"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_defaults_to_the_local_socket() {
+        assert_eq!(
+            DockerHost::parse(None),
+            DockerHost::Unix(String::from("/var/run/docker.sock"))
+        );
+    }
+
+    #[test]
+    fn parse_unix_socket() {
+        assert_eq!(
+            DockerHost::parse(Some("unix:///run/user/1000/docker.sock")),
+            DockerHost::Unix(String::from("/run/user/1000/docker.sock"))
+        );
+    }
+
+    #[test]
+    fn parse_tcp_address() {
+        assert_eq!(
+            DockerHost::parse(Some("tcp://10.0.0.2:2375")),
+            DockerHost::Tcp(String::from("10.0.0.2:2375"))
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use biscuit::{jwa, jws, ClaimsSet, RegisteredClaims, SingleOrMultiple, JWT};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use snafu::ResultExt;
 use std::str::FromStr;
+use uuid::Uuid;
 
 use crate::auth;
 use crate::error;
@@ -12,25 +13,41 @@ use crate::settings::Settings;
 #[derive(Clone, Debug)]
 pub struct Jwt {
     secret: String,
+    issuer: String,
     duration: chrono::Duration,
+    refresh_duration: chrono::Duration,
 }
 
 impl Jwt {
     pub fn new(settings: &Settings) -> Self {
         Self {
             secret: String::from(&settings.jwt.secret),
+            issuer: String::from(&settings.jwt.issuer),
             duration: chrono::Duration::minutes(settings.jwt.duration),
+            refresh_duration: chrono::Duration::minutes(settings.jwt.refresh_duration),
         }
     }
 
-    pub fn encode(&self, claims: auth::PrivateClaims) -> Result<String, error::Error> {
-        let expiry = Utc::now() + self.duration;
+    /// Issue an access token for the given subject, the id of the user.
+    /// Each token gets its own id (jti), so that it can be revoked before it expires.
+    pub fn encode(
+        &self,
+        subject: &str,
+        claims: auth::PrivateClaims,
+    ) -> Result<String, error::Error> {
+        let now = Utc::now();
+        let expiry = now + self.duration;
         let registered = RegisteredClaims {
-            issuer: Some(FromStr::from_str("https://www.acme.com").unwrap()),
-            subject: Some(FromStr::from_str("John Doe").unwrap()),
-            audience: Some(SingleOrMultiple::Single(
-                FromStr::from_str("htts://acme-customer.com").unwrap(),
-            )),
+            issuer: Some(
+                FromStr::from_str(&self.issuer).context(error::BiscuitError {
+                    msg: String::from("invalid jwt issuer"),
+                })?,
+            ),
+            subject: Some(FromStr::from_str(subject).context(error::BiscuitError {
+                msg: String::from("invalid jwt subject"),
+            })?),
+            id: Some(Uuid::new_v4().to_string()),
+            issued_at: Some(now.into()),
             expiry: Some(expiry.into()),
             ..Default::default()
         };
@@ -75,6 +92,45 @@ impl Jwt {
             })?
             //.private
             .to_owned();
-        Ok(payload)
+
+        // biscuit checks the signature, but neither the issuer, nor the expiry.
+        // Whether the token was revoked is checked against the database, by the caller.
+        let issuer = payload
+            .registered
+            .issuer
+            .as_ref()
+            .map(|issuer| issuer.to_string());
+        if issuer.as_deref() != Some(self.issuer.as_str()) {
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Token from an unknown issuer"),
+            });
+        }
+
+        if payload.registered.id.is_none() {
+            return Err(error::Error::Unauthenticated {
+                msg: String::from("Token without id"),
+            });
+        }
+
+        match payload.registered.expiry.as_ref() {
+            Some(expiry) if **expiry > Utc::now() => Ok(payload),
+            Some(_) => Err(error::Error::Unauthenticated {
+                msg: String::from("Token expired"),
+            }),
+            None => Err(error::Error::Unauthenticated {
+                msg: String::from("Token without expiry"),
+            }),
+        }
+    }
+
+    /// A new refresh token, with its expiry.
+    /// Refresh tokens are opaque random strings, stored hashed in the database.
+    pub fn refresh_token(&self) -> (String, DateTime<Utc>) {
+        let token = format!(
+            "{}{}",
+            Uuid::new_v4().to_simple(),
+            Uuid::new_v4().to_simple()
+        );
+        (token, Utc::now() + self.refresh_duration)
     }
 }
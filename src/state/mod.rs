@@ -1,16 +1,21 @@
 use argon::Argon;
 use bollard::Docker;
+use docker::DockerHost;
 use jwt::Jwt;
+use pulls::{pull_progress_channel, PullProgress};
 use slog::{info, o, Logger};
 use snafu::ResultExt;
 use sqlx::postgres::PgPool;
 use sqlx::prelude::PgQueryAs;
+use tokio::sync::broadcast;
 
 use crate::error;
-use crate::settings::Settings;
+use crate::settings::{Limits, Policy, Registry, Settings};
 
 pub mod argon;
+pub mod docker;
 pub mod jwt;
+pub mod pulls;
 
 #[derive(Clone, Debug)]
 pub struct State {
@@ -19,6 +24,13 @@ pub struct State {
     pub argon: Argon,
     pub jwt: Jwt,
     pub docker: Docker,
+    /// Where the docker engine listens, for the requests bollard cannot make
+    pub docker_host: DockerHost,
+    pub limits: Limits,
+    pub registry: Registry,
+    pub policy: Policy,
+    /// The progress of the image pulls, for subscribers
+    pub pulls: broadcast::Sender<PullProgress>,
 }
 
 impl State {
@@ -50,9 +62,8 @@ impl State {
         let argon = Argon::new(&settings);
         let jwt = Jwt::new(&settings);
 
-        let docker = Docker::connect_with_unix_defaults().context(error::BollardError {
-            msg: String::from("Could not establish connection with docker engine"),
-        })?;
+        let docker_host = DockerHost::from_env();
+        let docker = docker_host.connect()?;
 
         let version = docker.version().await.context(error::BollardError {
             msg: String::from("Could not get docker version"),
@@ -65,6 +76,11 @@ impl State {
             argon,
             jwt,
             docker,
+            docker_host,
+            limits: settings.limits.clone(),
+            registry: settings.registry.clone(),
+            policy: settings.policy.clone(),
+            pulls: pull_progress_channel(),
         })
     }
 }
@@ -0,0 +1,69 @@
+use bollard::service::CreateImageInfo;
+use juniper::GraphQLObject;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of progress messages kept for slow subscribers.
+const PULL_PROGRESS_CAPACITY: usize = 256;
+
+/// A progress message sent by the docker engine while pulling an image,
+/// eg 'Downloading' a layer, with the number of bytes received so far.
+#[derive(Debug, Clone, Deserialize, Serialize, GraphQLObject)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    /// The image being pulled
+    pub image: String,
+    /// The layer the message is about, if any
+    pub layer: Option<String>,
+    pub status: Option<String>,
+    /// A human readable progress bar
+    pub progress: Option<String>,
+    pub current: Option<f64>,
+    pub total: Option<f64>,
+    pub error: Option<String>,
+    /// Set on the last message of a pull
+    pub done: bool,
+}
+
+impl PullProgress {
+    pub fn new(image: &str, info: CreateImageInfo) -> Self {
+        let (current, total) = info
+            .progress_detail
+            .map(|detail| {
+                (
+                    detail.current.map(|current| current as f64),
+                    detail.total.map(|total| total as f64),
+                )
+            })
+            .unwrap_or_default();
+        PullProgress {
+            image: String::from(image),
+            layer: info.id,
+            status: info.status,
+            progress: info.progress,
+            current,
+            total,
+            error: info.error,
+            done: false,
+        }
+    }
+
+    pub fn done(image: &str, error: Option<String>) -> Self {
+        PullProgress {
+            image: String::from(image),
+            layer: None,
+            status: None,
+            progress: None,
+            current: None,
+            total: None,
+            error,
+            done: true,
+        }
+    }
+}
+
+/// The channel on which the progress of all the image pulls is broadcast.
+pub fn pull_progress_channel() -> broadcast::Sender<PullProgress> {
+    let (sender, _) = broadcast::channel(PULL_PROGRESS_CAPACITY);
+    sender
+}